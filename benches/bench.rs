@@ -1,8 +1,10 @@
+use std::sync::Arc;
+
 use criterion::{black_box, Criterion, criterion_group, criterion_main};
 use tokio::runtime::Runtime;
 use url::Url;
 
-use sitemap_generator::{analyze, Options, Validator};
+use sitemap_generator::{analyze, analyze_with_fetcher, MemoryFetcher, Options, Validator};
 
 fn benchmark(c: &mut Criterion) {
     let options = Options::builder().set_remove_query_and_fragment(true).build();
@@ -18,6 +20,26 @@ fn benchmark(c: &mut Criterion) {
     c.bench_function("sitemap_medium", |b| b.to_async(start_runtime()).iter(|| {
         analyze(black_box(javadocs.clone()), black_box(validator.clone()), black_box(options.clone()))
     }));
+
+    // Shared between the iterations, cloning the pages would take most of the time
+    let (fetcher, start) = memory_site(1000, 20);
+    let fetcher = Arc::new(fetcher);
+    let validator = Validator::new(std::iter::once(start.clone()));
+    c.bench_function("sitemap_offline", |b| b.to_async(start_runtime()).iter(|| {
+        analyze_with_fetcher(black_box(fetcher.clone()), black_box(std::iter::once(start.clone())), black_box(validator.clone()), black_box(options.clone()))
+    }));
+}
+
+/// Builds a site of `pages` pages, each one linking to the next `links` pages.
+fn memory_site(pages: usize, links: usize) -> (MemoryFetcher, Url) {
+    let base = Url::parse("https://example.com/").unwrap();
+    let fetcher = (0..pages).fold(MemoryFetcher::new(), |fetcher, page| {
+        let html: String = (1..=links)
+        .map(|link| format!(r#"<a href="/page/{}">Page</a>"#, (page + link) % pages))
+        .collect();
+        fetcher.with_page(base.join(&format!("/page/{}", page)).unwrap(), html)
+    });
+    (fetcher, base.join("/page/0").unwrap())
 }
 
 #[inline]
@@ -0,0 +1,91 @@
+use std::sync::Arc;
+
+use tokio::sync::watch;
+
+/// Handle used to stop a running crawl.
+///
+/// After cancellation, no new site is analyzed and the ones being analyzed are given a grace period to finish
+/// (see [`OptionsBuilder::set_shutdown_grace_period`](crate::OptionsBuilder::set_shutdown_grace_period)).
+/// The crawl then returns the sites analyzed so far: the other ones are only kept in the state file,
+/// see [`OptionsBuilder::set_state_file`](crate::OptionsBuilder::set_state_file).
+#[derive(Debug, Clone)]
+pub struct CancellationHandle {
+    // Using an Arc to allow cloning
+    sender: Arc<watch::Sender<bool>>,
+}
+
+impl CancellationHandle {
+    #[inline]
+    pub fn new() -> CancellationHandle {
+        CancellationHandle {
+            sender: Arc::new(watch::channel(false).0),
+        }
+    }
+
+    #[inline]
+    pub fn cancel(&self) {
+        self.sender.send_replace(true);
+    }
+
+    #[inline]
+    pub fn is_cancelled(&self) -> bool {
+        *self.sender.borrow()
+    }
+
+    /// Completes when the handle gets cancelled.
+    pub async fn cancelled(&self) {
+        let mut receiver = self.sender.subscribe();
+        while !*receiver.borrow_and_update() {
+            // The sender is owned by self, so it cannot be dropped while waiting
+            let _ = receiver.changed().await;
+        }
+    }
+}
+
+impl Default for CancellationHandle {
+    #[inline]
+    fn default() -> Self {
+        CancellationHandle::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use reqwest::StatusCode;
+
+    use super::*;
+    use crate::{MemoryFetcher, Options};
+    use crate::test_utils::{crawl, SlowFetcher, sorted_sites, url};
+
+    #[tokio::test]
+    async fn cancelled_crawl() {
+        let fetcher = SlowFetcher::new(MemoryFetcher::new()
+        .with_page(url("/"), r#"<a href="/redirect">Redirect</a>"#)
+        .with_redirect(url("/redirect"), &url("/page"), StatusCode::FOUND)
+        .with_page(url("/page"), r#"<a href="/slow">Slow</a>"#)
+        .with_page(url("/slow"), r#"<a href="/never">Never</a>"#));
+
+        let cancellation_handle = CancellationHandle::new();
+        let options = Options::builder()
+        .set_cancellation_handle(Some(cancellation_handle.clone()))
+        .set_shutdown_grace_period(Duration::from_millis(50))
+        .set_remove_error_pages(true)
+        .build();
+
+        // Cancelled once the other pages are analyzed and "/slow" is being fetched
+        let handle = cancellation_handle.clone();
+        let started = fetcher.started.clone();
+        tokio::spawn(async move {
+            started.notified().await;
+            handle.cancel();
+        });
+
+        let result = crawl(fetcher, options).await;
+
+        assert!(cancellation_handle.is_cancelled());
+        // "/slow" was still being analyzed, so it's left out
+        assert_eq!(sorted_sites(&result), ["https://example.com/", "https://example.com/page", "https://example.com/redirect"]);
+    }
+}
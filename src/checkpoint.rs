@@ -0,0 +1,216 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::{PageInfo, Sites};
+use crate::utils::{read_json, write_atomically};
+
+/// The progress of a crawl, which can be persisted to resume it later.
+///
+/// See [`OptionsBuilder::set_state_file`](crate::OptionsBuilder::set_state_file) and
+/// [`Options::set_resume_state`](crate::Options::set_resume_state).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CrawlState {
+    /// Sites which have already been analyzed
+    pub visited: Vec<Url>,
+    /// Sites which still have to be analyzed
+    pub frontier: Vec<FrontierEntry>,
+    /// The information about the visited sites, which is part of the result of the resumed crawl
+    #[serde(default)]
+    pub pages: HashMap<Url, PageInfo>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FrontierEntry {
+    pub url: Url,
+    /// The remaining depth of the crawl from this site
+    pub recursion: usize,
+}
+
+impl CrawlState {
+    #[inline]
+    pub fn load(path: impl AsRef<Path>) -> Result<CrawlState> {
+        read_json(path.as_ref(), "state")
+    }
+
+    /// Writes the state without blocking the crawl. A crawl interrupted while saving keeps the previous state.
+    pub async fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref().to_path_buf();
+        let json = serde_json::to_vec(self)?;
+        tokio::task::spawn_blocking(move || write_atomically(&path, &json)).await?
+    }
+}
+
+/// Keeps track of the sites being analyzed, so that they end up in the frontier of a [`CrawlState`].
+#[derive(Debug, Default)]
+pub(crate) struct Frontier {
+    in_flight: Mutex<HashMap<Arc<Url>, InFlight>>,
+}
+
+#[derive(Debug)]
+struct InFlight {
+    recursion: usize,
+    // Sites inserted while following redirects, which must be analyzed again on resume
+    redirects: Vec<Arc<Url>>,
+}
+
+impl Frontier {
+    pub(crate) fn start(&self, site: Arc<Url>, recursion: usize) {
+        self.in_flight.lock().unwrap().insert(site, InFlight {
+            recursion,
+            redirects: Vec::new(),
+        });
+    }
+
+    /// Must be called *before* inserting the redirect into the visited sites.
+    pub(crate) fn redirected(&self, site: &Url, redirect: Arc<Url>) {
+        if let Some(in_flight) = self.in_flight.lock().unwrap().get_mut(site) {
+            in_flight.redirects.push(redirect);
+        }
+    }
+
+    pub(crate) fn finish(&self, site: &Url) {
+        self.in_flight.lock().unwrap().remove(site);
+    }
+
+    /// The sites which haven't been analyzed, including the redirects found while analyzing them
+    pub(crate) fn pending(&self) -> Vec<Arc<Url>> {
+        self.in_flight.lock().unwrap().iter()
+        .flat_map(|(site, in_flight)| std::iter::once(site).chain(&in_flight.redirects).cloned())
+        .collect()
+    }
+
+    pub(crate) fn snapshot(&self, sites: &Sites, pages: &Mutex<HashMap<Arc<Url>, PageInfo>>) -> CrawlState {
+        // Sites must be read first: every redirect present in them is then guaranteed to be in in_flight too
+        let mut visited = sites.access_map(|hashset| hashset.clone());
+        let in_flight = self.in_flight.lock().unwrap();
+
+        let frontier = in_flight.iter().map(|(site, in_flight)| {
+            visited.remove(site);
+            for redirect in &in_flight.redirects {
+                visited.remove(redirect);
+            }
+            FrontierEntry {
+                url: (**site).clone(),
+                recursion: in_flight.recursion,
+            }
+        }).collect();
+
+        // Read while in_flight is locked, so that every visited site has its page. The pages of the frontier are fetched again,
+        // the removed error pages and redirects are kept like in the result of the crawl
+        let pages = pages.lock().unwrap().iter()
+        .filter(|(url, page)| visited.contains(*url) || page.is_error_or_redirect())
+        .map(|(url, page)| ((**url).clone(), page.clone()))
+        .collect();
+
+        CrawlState {
+            visited: visited.into_iter().map(|site| (*site).clone()).collect(),
+            frontier,
+            pages,
+        }
+    }
+}
+
+/// Serializes a [`StatusCode`] as its number
+pub(crate) mod status_code {
+    use reqwest::StatusCode;
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub(crate) fn serialize<S: Serializer>(status: &StatusCode, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u16(status.as_u16())
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<StatusCode, D::Error> {
+        StatusCode::from_u16(u16::deserialize(deserializer)?).map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    use reqwest::header::{CONTENT_TYPE, HeaderMap, HeaderValue, LAST_MODIFIED};
+    use reqwest::StatusCode;
+
+    use crate::{CancellationHandle, CrawlResult, MemoryFetcher, Options};
+    use crate::test_utils::{crawl, SlowFetcher, sorted_sites, url, with_headers};
+
+    #[tokio::test]
+    async fn resume_crawl() {
+        let fetcher = MemoryFetcher::new()
+        .with_page(url("/"), r#"<a href="/a">A</a>"#)
+        .with_page(url("/a"), r#"<a href="/b">B</a>"#)
+        .with_page(url("/b"), r#"<a href="/c">C</a> <a href="/d">D</a>"#);
+
+        // The crawl was interrupted while analyzing "/b", whose links must be found again
+        let state = CrawlState {
+            visited: vec![url("/"), url("/a"), url("/d")],
+            frontier: vec![FrontierEntry {
+                url: url("/b"),
+                recursion: 1,
+            }],
+            pages: HashMap::new(),
+        };
+        let mut options = Options::default();
+        options.set_resume_state(Some(state));
+
+        let result = crawl(fetcher, options).await;
+        assert_eq!(sorted_sites(&result), ["https://example.com/", "https://example.com/a", "https://example.com/b", "https://example.com/c", "https://example.com/d"]);
+    }
+
+    #[tokio::test]
+    async fn resumed_crawl_result() {
+        let fetcher = MemoryFetcher::new();
+        let fetcher = with_headers(fetcher, "/", &[(LAST_MODIFIED, "Mon, 02 May 2022 10:00:00 GMT")], r#"<a href="/a">A</a> <a href="/slow">Slow</a> <img src="/logo.png">"#);
+        let fetcher = with_headers(fetcher, "/a", &[(LAST_MODIFIED, "Tue, 03 May 2022 10:00:00 GMT")], r#"<a href="/gone">Gone</a>"#);
+        let fetcher = with_headers(fetcher, "/slow", &[], r#"<a href="/b">B</a>"#)
+        .with_response(url("/gone"), StatusCode::GONE, HeaderMap::new(), "")
+        .with_page(url("/b"), "B");
+        let options = || Options::builder().set_collect_images(true).set_collect_links(true);
+        let expected = crawl(fetcher.clone(), options().build()).await;
+
+        let state_file = std::env::temp_dir().join(format!("sitemap_generator_state_{}.json", std::process::id()));
+        let cancellation_handle = CancellationHandle::new();
+        let interrupted = options()
+        .set_state_file(Some(state_file.clone()))
+        .set_cancellation_handle(Some(cancellation_handle.clone()))
+        .set_shutdown_grace_period(Duration::from_millis(50))
+        .build();
+        // Cancelled once "/" is analyzed and "/slow" is being fetched
+        let slow_fetcher = SlowFetcher::new(fetcher.clone());
+        let handle = cancellation_handle.clone();
+        let started = slow_fetcher.started.clone();
+        tokio::spawn(async move {
+            started.notified().await;
+            handle.cancel();
+        });
+        crawl(slow_fetcher, interrupted).await;
+        let state = CrawlState::load(&state_file);
+        std::fs::remove_file(&state_file).unwrap();
+        let state = state.unwrap();
+        assert!(state.pages.contains_key(&url("/")));
+
+        // The pages which have already been visited aren't fetched again
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("text/html"));
+        let fetcher = fetcher.with_response(url("/"), StatusCode::INTERNAL_SERVER_ERROR, headers, "");
+        let mut options = options().build();
+        options.set_resume_state(Some(state));
+        let resumed = crawl(fetcher, options).await;
+
+        assert_eq!(sorted_sites(&resumed), sorted_sites(&expected));
+        let pages = |result: &CrawlResult| {
+            let mut pages: Vec<String> = result.pages.iter()
+            .map(|(url, page)| format!("{} {} {:?} {:?} {:?} {}", url, page.status, page.last_modified, page.images, page.links, page.depth))
+            .collect();
+            pages.sort();
+            pages
+        };
+        assert_eq!(pages(&resumed), pages(&expected));
+    }
+}
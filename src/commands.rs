@@ -0,0 +1,266 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use sitemap_generator::{check_existence, DirectoryFetcher, DiscoveredSites, fetch_sitemap, Link, PageInfo, ReqwestFetcher, Sitemap, SitemapEntry, SitemapKind, validate_sitemap, Validator};
+use sitemap_generator::utils::{read_json, write_atomically};
+
+use crate::input::{Crawl, DiffTarget, OtherOptions, RemovalThreshold, SitemapSource};
+use crate::{run_crawls, PARTIAL_EXIT_CODE, REMOVALS_EXIT_CODE};
+
+/// Writes to `path`, or to stdout if it's `None`
+pub(super) fn create_output(path: Option<&Path>) -> Result<Box<dyn Write>> {
+    Ok(match path {
+        Some(path) => Box::new(BufWriter::new(File::create(path).with_context(|| format!(r#"Cannot create "{}""#, path.display()))?)),
+        None => Box::new(BufWriter::new(std::io::stdout().lock())),
+    })
+}
+
+pub(super) fn load_sitemap(path: &Path) -> Result<Sitemap> {
+    let xml = std::fs::read(path).with_context(|| format!(r#"Cannot read "{}""#, path.display()))?;
+    Sitemap::parse(&xml).with_context(|| format!(r#"Invalid sitemap "{}""#, path.display()))
+}
+
+/// What is kept about a page between two updates of a sitemap
+#[derive(Debug, Serialize, Deserialize)]
+pub(super) struct CachedPage {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) etag: Option<String>,
+    /// Followed if the page hasn't changed at the next update
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(super) links: Vec<Link>,
+}
+
+/// Loads the cache of the pages of the sitemap being updated. It's empty before the first update
+pub(super) fn load_update_cache(path: &Path) -> Result<HashMap<Url, CachedPage>> {
+    if !path.exists() {
+        return Ok(HashMap::new());
+    }
+    read_json(path, "cache")
+}
+
+/// Replaces the cache with the pages of the crawl, leaving out the error pages and the redirects
+pub(super) fn save_update_cache(path: &Path, pages: &HashMap<Arc<Url>, PageInfo>) -> Result<()> {
+    let cache: BTreeMap<&Url, CachedPage> = pages.iter()
+    .filter(|(_, page)| !page.is_error_or_redirect())
+    .map(|(url, page)| (&**url, CachedPage {
+        etag: page.etag.clone(),
+        links: page.links.clone(),
+    }))
+    .collect();
+    write_atomically(path, &serde_json::to_vec(&cache)?)
+}
+
+/// Prints the violations of the protocol found in the files. If the files are published at `base_url`,
+/// their URLs are also checked to be under their location. Returns the exit code
+pub(super) fn validate(files: &[impl AsRef<Path>], base_url: Option<&Url>) -> i32 {
+    let mut exit_code = 0;
+    for file in files {
+        let file = file.as_ref();
+        let xml = match std::fs::read(file) {
+            Ok(xml) => xml,
+            Err(err) => {
+                eprintln!(r#"Cannot read "{}": {}"#, file.display(), err);
+                exit_code = 1;
+                continue;
+            },
+        };
+        let location = match (base_url, file.file_name().and_then(|name| name.to_str())) {
+            (Some(base_url), Some(name)) => base_url.join(name).ok(),
+            _ => None,
+        };
+
+        let issues = validate_sitemap(&xml, location.as_ref());
+        if issues.is_empty() {
+            println!("{}: valid", file.display());
+        } else {
+            exit_code = 1;
+            for issue in issues {
+                println!("{}:{}: {}", file.display(), issue.line, issue.problem);
+            }
+        }
+    }
+    exit_code
+}
+
+fn load_sitemap_source(source: &SitemapSource) -> Result<Sitemap> {
+    match source {
+        SitemapSource::File(path) => load_sitemap(path),
+        SitemapSource::Url(url) => {
+            let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+            runtime.block_on(fetch_sitemap(&ReqwestFetcher::new(), url))
+        },
+    }
+}
+
+/// Prints the URLs added ("+"), removed ("-") and modified ("~") since `old`. Returns the exit code
+pub(super) fn diff(old: &SitemapSource, new: DiffTarget, json: bool, max_removed: Option<RemovalThreshold>) -> i32 {
+    let old = match load_sitemap_source(old) {
+        Ok(old) => old,
+        Err(err) => return error(err),
+    };
+    let (new, partial) = match new {
+        DiffTarget::Sitemap(source) => match load_sitemap_source(&source) {
+            Ok(new) => (new, false),
+            Err(err) => return error(err),
+        },
+        DiffTarget::Crawl(crawls, other_options) => {
+            let output = run_crawls(crawls, *other_options);
+            (output.sitemap, output.partial)
+        },
+    };
+
+    let diff = old.diff(&new);
+    if json {
+        if let Err(err) = serde_json::to_writer_pretty(std::io::stdout().lock(), &diff) {
+            return error(err.into());
+        }
+        println!();
+    } else {
+        for url in &diff.added {
+            println!("+ {}", url);
+        }
+        for url in &diff.removed {
+            println!("- {}", url);
+        }
+        for change in &diff.changed {
+            println!("~ {} ({} -> {})", change.url, change.old_lastmod, change.new_lastmod);
+        }
+    }
+    eprintln!("{} added, {} removed, {} modified", diff.added.len(), diff.removed.len(), diff.changed.len());
+
+    if max_removed.is_some_and(|max_removed| max_removed.is_exceeded(diff.removed.len(), old.entries.len())) {
+        eprintln!("Too many URLs have been removed");
+        REMOVALS_EXIT_CODE
+    } else if partial {
+        PARTIAL_EXIT_CODE
+    } else {
+        0
+    }
+}
+
+/// Loads the URLs of a reference list, either a sitemap or a text file with one URL per line
+fn load_reference(source: &SitemapSource) -> Result<Vec<Url>> {
+    let path = match source {
+        SitemapSource::File(path) => path,
+        SitemapSource::Url(_) => return Ok(load_sitemap_source(source)?.entries.into_iter().map(|entry| entry.loc).collect()),
+    };
+    let content = std::fs::read(path).with_context(|| format!(r#"Cannot read "{}""#, path.display()))?;
+    if content.trim_ascii_start().starts_with(b"<") {
+        let sitemap = Sitemap::parse(&content).with_context(|| format!(r#"Invalid sitemap "{}""#, path.display()))?;
+        if sitemap.kind == SitemapKind::Index {
+            bail!(r#""{}" is a sitemap index, provide its URL instead"#, path.display());
+        }
+        return Ok(sitemap.entries.into_iter().map(|entry| entry.loc).collect());
+    }
+
+    let content = String::from_utf8(content).with_context(|| format!(r#""{}" is not valid UTF-8"#, path.display()))?;
+    content.lines()
+    .enumerate()
+    .map(|(index, line)| (index + 1, line.trim()))
+    .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+    .map(|(line_number, line)| Url::parse(line).with_context(|| format!(r#"{}:{}: invalid URL "{}""#, path.display(), line_number, line)))
+    .collect()
+}
+
+/// Crawls the sites, then prints the URLs of `reference` which haven't been found.
+/// With `check`, their status is printed too. Returns the exit code
+pub(super) fn orphans(reference: &SitemapSource, check: bool, crawls: Vec<Crawl>, other_options: OtherOptions, discovered: &DiscoveredSites) -> i32 {
+    let reference = match load_reference(reference) {
+        Ok(reference) => reference,
+        Err(err) => return error(err),
+    };
+    let validator = Validator::new(crawls.iter().flat_map(|crawl| crawl.sites_to_analyze.iter().cloned()));
+    let concurrency = crawls.iter().map(|crawl| crawl.options.max_task_count()).max().unwrap_or(1);
+    let directory = other_options.directory.clone().zip(crawls.first().and_then(|crawl| crawl.sites_to_analyze.iter().next().cloned()));
+    let pretty_urls = other_options.pretty_urls;
+
+    let total = reference.len();
+    let partial = run_crawls(crawls, other_options).partial;
+    let report = discovered.orphans(reference, &validator);
+    for url in &report.out_of_scope {
+        eprintln!(r#""{}" is not inside the sites to analyze"#, url);
+    }
+
+    if check {
+        let runtime = match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+            Ok(runtime) => runtime,
+            Err(err) => return error(err.into()),
+        };
+        let statuses = match directory {
+            Some((directory, base_url)) => {
+                let fetcher = DirectoryFetcher::new(directory, base_url).set_pretty_urls(pretty_urls);
+                runtime.block_on(check_existence(&fetcher, &report.orphans, concurrency))
+            },
+            None => runtime.block_on(check_existence(&ReqwestFetcher::new(), &report.orphans, concurrency)),
+        };
+        for (url, status) in statuses {
+            match status {
+                Ok(status) => println!("{}\t{}", url, status),
+                Err(err) => println!("{}\t{}", url, err),
+            }
+        }
+    } else {
+        for url in &report.orphans {
+            println!("{}", url);
+        }
+    }
+    eprintln!("{} orphan pages out of {} reference URLs", report.orphans.len(), total);
+
+    if partial {
+        PARTIAL_EXIT_CODE
+    } else {
+        0
+    }
+}
+
+/// Combines the sitemaps in `files`, or writes an index listing them if `index` is the URL where they are published.
+/// Returns the exit code
+pub(super) fn merge(files: &[impl AsRef<Path>], index: Option<Url>, output: Option<&Path>) -> i32 {
+    let sitemaps = match files.iter().map(|file| load_sitemap(file.as_ref())).collect::<Result<Vec<_>>>() {
+        Ok(sitemaps) => sitemaps,
+        Err(err) => return error(err),
+    };
+
+    let merged = match index {
+        Some(base_url) => index_of(files, &sitemaps, &base_url),
+        None => Sitemap::merge(sitemaps),
+    };
+    let result = merged.and_then(|merged| {
+        let mut writer = create_output(output)?;
+        merged.write(&mut writer)?;
+        writer.flush()?;
+        Ok(())
+    });
+    match result {
+        Ok(()) => 0,
+        Err(err) => error(err),
+    }
+}
+
+/// Creates an index listing the sitemaps, published at `base_url`
+fn index_of(files: &[impl AsRef<Path>], sitemaps: &[Sitemap], base_url: &Url) -> Result<Sitemap> {
+    let entries = files.iter().zip(sitemaps).map(|(file, sitemap)| {
+        let file = file.as_ref();
+        let name = file.file_name().and_then(|name| name.to_str()).with_context(|| format!(r#"Invalid file name "{}""#, file.display()))?;
+        Ok(SitemapEntry {
+            lastmod: sitemap.latest_lastmod().map(str::to_string),
+            ..SitemapEntry::new(base_url.join(name)?)
+        })
+    }).collect::<Result<_>>()?;
+    Ok(Sitemap {
+        kind: SitemapKind::Index,
+        entries,
+    })
+}
+
+fn error(err: anyhow::Error) -> i32 {
+    eprintln!("{:#}", err);
+    1
+}
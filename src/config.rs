@@ -0,0 +1,309 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use clap::ArgEnum;
+use serde::{Deserialize, Serialize};
+
+use sitemap_generator::feed::DEFAULT_FEED_SIZE;
+use sitemap_generator::SortOrder;
+
+/// Files looked for in the current directory when no config file is provided
+const DEFAULT_CONFIG_FILES: [&str; 3] = ["sitemap.toml", "sitemap.yaml", "sitemap.yml"];
+
+pub(super) const DEFAULT_MAX_DEPTH: usize = 50;
+pub(super) const DEFAULT_CHECKPOINT_INTERVAL: u64 = 30;
+pub(super) const DEFAULT_GRACE_PERIOD: u64 = 10;
+pub(super) const DEFAULT_TIMEOUT: u64 = 30;
+
+/// How the sitemap is printed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ArgEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(super) enum OutputFormat {
+    /// One URL per line
+    Plain,
+    /// One URL per line, written to files of at most 50,000 URLs and 50 MB
+    Txt,
+    /// The XML format of the sitemaps.org protocol
+    Xml,
+    /// A Google News sitemap, listing the articles published in the last 48 hours
+    News,
+    /// A page for users, listing the pages as a tree labelled with their titles, sorted by path
+    Html,
+    /// The information collected about the crawled pages, as a JSON array
+    Json,
+    /// Like json, with one object per line, written as soon as every page is analyzed so not sorted
+    Ndjson,
+    /// Like json, as comma-separated values
+    Csv,
+    /// An Atom feed of the most recently modified pages
+    Atom,
+    /// An RSS 2.0 feed of the most recently modified pages
+    Rss,
+}
+
+impl OutputFormat {
+    /// Whether the format describes the crawled pages, see records.rs
+    #[inline]
+    pub(super) fn is_record(self) -> bool {
+        matches!(self, OutputFormat::Json | OutputFormat::Ndjson | OutputFormat::Csv)
+    }
+
+    #[inline]
+    pub(super) fn is_feed(self) -> bool {
+        matches!(self, OutputFormat::Atom | OutputFormat::Rss)
+    }
+}
+
+/// The content of a config file. Every value can also be provided from the command line, which takes precedence.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub(super) struct Config {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(super) sites: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) starting_points: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) skip_sites_as_starting_points: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) additional_links: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) max_concurrent_tasks: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) remove_query_and_fragment: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) max_depth: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) max_body_size: Option<usize>,
+    /// In seconds, 0 to wait forever
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) timeout: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) images: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) image_sites: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) videos: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) hreflang: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) priority: Option<bool>,
+    /// Like `/blog/*=daily`, the first matching rule is used
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) changefreq_rules: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) lastmod_history: Option<PathBuf>,
+    /// The format is chosen from the extension: .dot or .gv, .graphml, .json
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) graph: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) format: Option<OutputFormat>,
+    /// `lexicographic`, `depth` or `discovery`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) sort: Option<SortOrder>,
+    /// The name of the publication, for the news format
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) publication_name: Option<String>,
+    /// Used for the articles whose language cannot be detected, for the news format
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) publication_language: Option<String>,
+    /// The number of pages of the atom and rss formats
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) feed_size: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) feed_title: Option<String>,
+    /// Template of the html format, `{{tree}}` is replaced by the list of the pages
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) html_template: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) output: Option<PathBuf>,
+    /// An XML sitemap whose pages are requested conditionally, keeping their lastmod when they haven't changed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) update: Option<PathBuf>,
+    /// The ETags and the links of the pages, saved after every update
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) update_cache: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) directory: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) pretty_urls: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) state_file: Option<PathBuf>,
+    /// In seconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) checkpoint_interval: Option<u64>,
+    /// In seconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) grace_period: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) progress: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) verbose: Option<bool>,
+    /// Overrides for the crawl of a site, which must be one of `sites`
+    #[serde(rename = "site", skip_serializing_if = "BTreeMap::is_empty")]
+    pub(super) site_overrides: BTreeMap<String, SiteConfig>,
+}
+
+/// The settings which can be overridden for a single site. The site is then crawled on its own.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub(super) struct SiteConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) starting_points: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) additional_links: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) max_concurrent_tasks: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) remove_query_and_fragment: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) max_depth: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(super) max_body_size: Option<usize>,
+}
+
+impl Config {
+    /// Loads a TOML or YAML file, depending on its extension
+    pub(super) fn load(path: &Path) -> Result<Config> {
+        let content = std::fs::read_to_string(path).with_context(|| format!(r#"Cannot read config file "{}""#, path.display()))?;
+        let config = match path.extension().and_then(|extension| extension.to_str()) {
+            Some("yaml" | "yml") => serde_yaml::from_str(&content).map_err(anyhow::Error::from),
+            _ => toml::from_str(&content).map_err(anyhow::Error::from),
+        };
+        config.with_context(|| format!(r#"Invalid config file "{}""#, path.display()))
+    }
+
+    /// Loads the first of the default config files found in the current directory
+    pub(super) fn discover() -> Result<Option<Config>> {
+        DEFAULT_CONFIG_FILES.iter()
+        .map(Path::new)
+        .find(|path| path.is_file())
+        .map(Config::load)
+        .transpose()
+    }
+
+    /// Merges two configs, values of `self` take precedence over the ones of `other`
+    pub(super) fn or(self, other: Config) -> Config {
+        let mut site_overrides = other.site_overrides;
+        site_overrides.extend(self.site_overrides);
+        Config {
+            sites: if self.sites.is_empty() { other.sites } else { self.sites },
+            starting_points: self.starting_points.or(other.starting_points),
+            skip_sites_as_starting_points: self.skip_sites_as_starting_points.or(other.skip_sites_as_starting_points),
+            additional_links: self.additional_links.or(other.additional_links),
+            max_concurrent_tasks: self.max_concurrent_tasks.or(other.max_concurrent_tasks),
+            remove_query_and_fragment: self.remove_query_and_fragment.or(other.remove_query_and_fragment),
+            max_depth: self.max_depth.or(other.max_depth),
+            max_body_size: self.max_body_size.or(other.max_body_size),
+            timeout: self.timeout.or(other.timeout),
+            images: self.images.or(other.images),
+            image_sites: self.image_sites.or(other.image_sites),
+            videos: self.videos.or(other.videos),
+            hreflang: self.hreflang.or(other.hreflang),
+            priority: self.priority.or(other.priority),
+            changefreq_rules: self.changefreq_rules.or(other.changefreq_rules),
+            lastmod_history: self.lastmod_history.or(other.lastmod_history),
+            graph: self.graph.or(other.graph),
+            format: self.format.or(other.format),
+            sort: self.sort.or(other.sort),
+            publication_name: self.publication_name.or(other.publication_name),
+            publication_language: self.publication_language.or(other.publication_language),
+            feed_size: self.feed_size.or(other.feed_size),
+            feed_title: self.feed_title.or(other.feed_title),
+            html_template: self.html_template.or(other.html_template),
+            output: self.output.or(other.output),
+            update: self.update.or(other.update),
+            update_cache: self.update_cache.or(other.update_cache),
+            directory: self.directory.or(other.directory),
+            pretty_urls: self.pretty_urls.or(other.pretty_urls),
+            state_file: self.state_file.or(other.state_file),
+            checkpoint_interval: self.checkpoint_interval.or(other.checkpoint_interval),
+            grace_period: self.grace_period.or(other.grace_period),
+            progress: self.progress.or(other.progress),
+            verbose: self.verbose.or(other.verbose),
+            site_overrides,
+        }
+    }
+
+    /// Fills the missing values with the defaults
+    pub(super) fn with_defaults(self) -> Config {
+        Config {
+            skip_sites_as_starting_points: self.skip_sites_as_starting_points.or(Some(false)),
+            max_concurrent_tasks: self.max_concurrent_tasks.or_else(|| Some(num_cpus::get())),
+            remove_query_and_fragment: self.remove_query_and_fragment.or(Some(false)),
+            max_depth: self.max_depth.or(Some(DEFAULT_MAX_DEPTH)),
+            timeout: self.timeout.or(Some(DEFAULT_TIMEOUT)),
+            images: self.images.or(Some(false)),
+            videos: self.videos.or(Some(false)),
+            hreflang: self.hreflang.or(Some(false)),
+            priority: self.priority.or(Some(false)),
+            format: self.format.or(Some(OutputFormat::Plain)),
+            sort: self.sort.or(Some(SortOrder::Lexicographic)),
+            feed_size: self.feed_size.or(Some(DEFAULT_FEED_SIZE)),
+            pretty_urls: self.pretty_urls.or(Some(false)),
+            checkpoint_interval: self.checkpoint_interval.or(Some(DEFAULT_CHECKPOINT_INTERVAL)),
+            grace_period: self.grace_period.or(Some(DEFAULT_GRACE_PERIOD)),
+            progress: self.progress.or(Some(true)),
+            verbose: self.verbose.or(Some(false)),
+            ..self
+        }
+    }
+
+    #[inline]
+    pub(super) fn to_toml(&self) -> Result<String> {
+        Ok(toml::to_string(self)?)
+    }
+}
+
+impl SiteConfig {
+    /// Applies the overrides on top of the global config
+    pub(super) fn apply(&self, config: &Config) -> Config {
+        Config {
+            starting_points: self.starting_points.clone().or_else(|| config.starting_points.clone()),
+            additional_links: self.additional_links.clone(),
+            max_concurrent_tasks: self.max_concurrent_tasks.or(config.max_concurrent_tasks),
+            remove_query_and_fragment: self.remove_query_and_fragment.or(config.remove_query_and_fragment),
+            max_depth: self.max_depth.or(config.max_depth),
+            max_body_size: self.max_body_size.or(config.max_body_size),
+            site_overrides: BTreeMap::new(),
+            ..config.clone()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_file() {
+        let toml = r#"
+        sites = ["https://example.com/"]
+        max-depth = 10
+
+        [site."https://example.com/blog/"]
+        max-depth = 2
+    "#;
+        let yaml = "
+            sites: [\"https://example.com/\"]
+            max-depth: 10
+            site:
+              https://example.com/blog/:
+                max-depth: 2
+        ";
+
+        for config in [toml::from_str::<Config>(toml).unwrap(), serde_yaml::from_str::<Config>(yaml).unwrap()] {
+            let cli = Config {
+                max_depth: Some(5),
+                ..Config::default()
+            };
+            let config = cli.or(config).with_defaults();
+            assert_eq!(config.sites, ["https://example.com/"]);
+            assert_eq!(config.max_depth, Some(5));
+            assert_eq!(config.grace_period, Some(DEFAULT_GRACE_PERIOD));
+            assert_eq!(config.site_overrides["https://example.com/blog/"].apply(&config).max_depth, Some(2));
+        }
+
+        assert!(toml::from_str::<Config>("unknown-key = 1").is_err());
+    }
+}
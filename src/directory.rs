@@ -0,0 +1,392 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{bail, Result};
+use futures::{stream, StreamExt};
+use percent_encoding::percent_decode_str;
+use reqwest::header::{CONTENT_TYPE, HeaderMap, HeaderValue, LAST_MODIFIED, LOCATION};
+use reqwest::StatusCode;
+use url::Url;
+
+use crate::{analyze_with_fetcher, CrawlError, Fetcher, FetchResponse, Options, PageInfo, Validator};
+use crate::utils::format_http_date;
+
+/// A [`Fetcher`] serving the files of a local directory, such as the output of a static site generator.
+///
+/// URLs under `base_url` are mapped to the files inside the directory: `index.html` (or `index.htm`) files
+/// are served for directory URLs, and `page.html` is served for `page` when no file or directory named `page` exists.
+/// Links to `index.html` files are redirected to their directory.
+/// Requested URLs without a matching file are collected and can be retrieved with [`missing`](DirectoryFetcher::missing).
+#[derive(Debug, Clone)]
+pub struct DirectoryFetcher {
+    root: PathBuf,
+    base_url: Url,
+    pretty_urls: bool,
+    crawl_all_files: bool,
+    // Shared between clones, so that missing files can be retrieved after the crawl
+    missing: Arc<Mutex<HashSet<Url>>>,
+}
+
+pub struct DirectoryAnalysis {
+    pub sites: Vec<Arc<Url>>,
+    /// Links pointing to files which don't exist, sorted by URL
+    pub missing: Vec<MissingFile>,
+    /// The sites which couldn't be analyzed, see [`CrawlResult::errors`](crate::CrawlResult::errors)
+    pub errors: Vec<(Arc<Url>, CrawlError)>,
+    /// Information about the files, see [`CrawlResult::pages`](crate::CrawlResult::pages)
+    pub pages: HashMap<Arc<Url>, PageInfo>,
+}
+
+/// A requested URL which didn't match any file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MissingFile {
+    pub url: Url,
+    /// The page linking to the file, `None` if it's a starting point
+    pub referrer: Option<Url>,
+}
+
+/// Crawls the HTML files inside the directory of `fetcher`, together with the provided starting points.
+/// See [`set_crawl_all_files`](DirectoryFetcher::set_crawl_all_files) to crawl only the linked files.
+///
+/// The missing files are removed from the sitemap and only the HTML files are analyzed, see
+/// [`set_remove_error_pages`](crate::OptionsBuilder::set_remove_error_pages) and [`set_analyze_html_only`](crate::OptionsBuilder::set_analyze_html_only).
+pub async fn analyze_directory(fetcher: DirectoryFetcher, starting_points: impl Iterator<Item=Url>, mut options: Options) -> Result<DirectoryAnalysis> {
+    options.check_responses();
+    let pages = if fetcher.crawl_all_files {
+        let fetcher = fetcher.clone();
+        tokio::task::spawn_blocking(move || fetcher.pages()).await??
+    } else {
+        Vec::new()
+    };
+    let validator = Validator::new(std::iter::once(fetcher.base_url.clone()));
+    let missing = fetcher.missing.clone();
+
+    let result = analyze_with_fetcher(fetcher, starting_points.chain(pages), validator, options).await;
+
+    let mut missing: Vec<MissingFile> = missing.lock().unwrap().drain()
+    .map(|url| {
+        let referrer = result.pages.get(&url).and_then(|page| page.referrer.clone());
+        MissingFile { url, referrer }
+    })
+    .collect();
+    missing.sort_by(|file, other| file.url.cmp(&other.url));
+    Ok(DirectoryAnalysis {
+        sites: result.sites.into_iter().collect(),
+        missing,
+        errors: result.errors,
+        pages: result.pages,
+    })
+}
+
+impl DirectoryFetcher {
+    pub fn new(root: impl Into<PathBuf>, mut base_url: Url) -> DirectoryFetcher {
+        base_url.set_query(None);
+        base_url.set_fragment(None);
+        if !base_url.path().ends_with('/') {
+            let path = format!("{}/", base_url.path());
+            base_url.set_path(&path);
+        }
+        DirectoryFetcher {
+            root: root.into(),
+            base_url,
+            pretty_urls: false,
+            crawl_all_files: true,
+            missing: Default::default(),
+        }
+    }
+
+    /// When enabled, `page.html` files are mapped to the `page` URL instead of `page.html`,
+    /// and links to `page.html` are redirected to `page`.
+    #[inline]
+    pub fn set_pretty_urls(mut self, pretty_urls: bool) -> DirectoryFetcher {
+        self.pretty_urls = pretty_urls;
+        self
+    }
+
+    /// When disabled, [`analyze_directory`] crawls only the files reachable from the starting points,
+    /// instead of using every HTML file as a starting point. Enabled by default.
+    #[inline]
+    pub fn set_crawl_all_files(mut self, crawl_all_files: bool) -> DirectoryFetcher {
+        self.crawl_all_files = crawl_all_files;
+        self
+    }
+
+    #[inline]
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    #[inline]
+    pub fn base_url(&self) -> &Url {
+        &self.base_url
+    }
+
+    #[inline]
+    pub fn pretty_urls(&self) -> bool {
+        self.pretty_urls
+    }
+
+    #[inline]
+    pub fn crawl_all_files(&self) -> bool {
+        self.crawl_all_files
+    }
+
+    /// The requested URLs which didn't match any file, sorted.
+    pub fn missing(&self) -> Vec<Url> {
+        let mut missing: Vec<Url> = self.missing.lock().unwrap().iter().cloned().collect();
+        missing.sort();
+        missing
+    }
+
+    /// Walks the directory, returning the public URLs of every HTML file inside it.
+    pub fn pages(&self) -> Result<Vec<Url>> {
+        fn walk(dir: &Path, segments: &mut Vec<String>, fetcher: &DirectoryFetcher, pages: &mut Vec<Url>) -> Result<()> {
+            let mut entries = std::fs::read_dir(dir)?.collect::<std::io::Result<Vec<_>>>()?;
+            entries.sort_by_key(|entry| entry.file_name());
+            for entry in entries {
+                let name = match entry.file_name().into_string() {
+                    Ok(name) => name,
+                    Err(_) => continue, // Not representable in a URL
+                };
+                let file_type = entry.file_type()?;
+                if file_type.is_dir() {
+                    segments.push(name);
+                    walk(&entry.path(), segments, fetcher, pages)?;
+                    segments.pop();
+                } else if is_html_file(&name) {
+                    segments.push(name);
+                    pages.push(fetcher.file_url(segments));
+                    segments.pop();
+                }
+            }
+            Ok(())
+        }
+
+        if !self.root.is_dir() {
+            bail!(r#""{}" is not a directory"#, self.root.display());
+        }
+        let mut pages = Vec::new();
+        walk(&self.root, &mut Vec::new(), self, &mut pages)?;
+        Ok(pages)
+    }
+
+    fn file_url(&self, segments: &[String]) -> Url {
+        let mut url = self.base_url.clone();
+        {
+            let mut path = url.path_segments_mut().expect("base URL is a valid base");
+            path.pop_if_empty();
+            let (name, dirs) = segments.split_last().expect("at least the file name is present");
+            path.extend(dirs);
+            if name == "index.html" || name == "index.htm" {
+                path.push("");
+            } else if let Some(stem) = name.strip_suffix(".html").or_else(|| name.strip_suffix(".htm")).filter(|_| self.pretty_urls) {
+                path.push(stem);
+            } else {
+                path.push(name);
+            }
+        }
+        url
+    }
+
+    async fn resolve(&self, url: &Url) -> Resolved {
+        let relative = match url.path().strip_prefix(self.base_url.path()) {
+            Some(relative) if url.origin() == self.base_url.origin() => relative,
+            _ => return Resolved::Missing,
+        };
+
+        let mut path = self.root.clone();
+        let mut segments = Vec::new();
+        for segment in relative.split('/').filter(|segment| !segment.is_empty()) {
+            let segment = percent_decode_str(segment).decode_utf8_lossy();
+            if segment == "." || segment == ".." || segment.contains(['/', '\\']) {
+                return Resolved::Missing;
+            }
+            path.push(&*segment);
+            segments.push(segment.into_owned());
+        }
+
+        if relative.is_empty() || relative.ends_with('/') {
+            for index in ["index.html", "index.htm"] {
+                let index = path.join(index);
+                if is_file(&index).await {
+                    return Resolved::File(index);
+                }
+            }
+            return Resolved::Missing;
+        }
+        if is_file(&path).await {
+            // Redirect `index.html` to its directory and, with pretty URLs, `page.html` to `page`,
+            // so that every page is listed once
+            let canonical = self.file_url(&segments);
+            if is_html_file(&segments[segments.len() - 1]) && canonical.path() != url.path() {
+                let mut redirect = url.clone();
+                redirect.set_path(canonical.path());
+                return Resolved::Redirect(redirect);
+            }
+            return Resolved::File(path);
+        }
+        if tokio::fs::metadata(&path).await.is_ok_and(|metadata| metadata.is_dir()) {
+            // Like web servers do, redirect to the URL ending with a slash
+            let mut redirect = url.clone();
+            redirect.set_path(&format!("{}/", url.path()));
+            return Resolved::Redirect(redirect);
+        }
+        for extension in [".html", ".htm"] {
+            let mut html = path.clone().into_os_string();
+            html.push(extension);
+            let html = PathBuf::from(html);
+            if is_file(&html).await {
+                return Resolved::File(html);
+            }
+        }
+        Resolved::Missing
+    }
+}
+
+enum Resolved {
+    File(PathBuf),
+    Redirect(Url),
+    Missing,
+}
+
+impl Fetcher for DirectoryFetcher {
+    async fn fetch(&self, url: &Url) -> Result<FetchResponse> {
+        let mut headers = HeaderMap::new();
+        let (status, body) = match self.resolve(url).await {
+            Resolved::File(path) => {
+                headers.insert(CONTENT_TYPE, HeaderValue::from_static(content_type(&path)));
+                if let Ok(modified) = tokio::fs::metadata(&path).await.and_then(|metadata| metadata.modified()) {
+                    headers.insert(LAST_MODIFIED, HeaderValue::from_str(&format_http_date(&modified.into()))?);
+                }
+                (StatusCode::OK, tokio::fs::read(&path).await?)
+            },
+            Resolved::Redirect(location) => {
+                headers.insert(LOCATION, HeaderValue::from_str(location.as_str())?);
+                (StatusCode::MOVED_PERMANENTLY, Vec::new())
+            },
+            Resolved::Missing => {
+                self.missing.lock().unwrap().insert(url.clone());
+                (StatusCode::NOT_FOUND, Vec::new())
+            },
+        };
+        Ok(FetchResponse {
+            status,
+            headers,
+            url: url.clone(),
+            body: stream::once(async move { Ok(body.into()) }).boxed(),
+        })
+    }
+}
+
+#[inline]
+async fn is_file(path: &Path) -> bool {
+    tokio::fs::metadata(path).await.is_ok_and(|metadata| metadata.is_file())
+}
+
+#[inline]
+fn is_html_file(name: &str) -> bool {
+    name.ends_with(".html") || name.ends_with(".htm")
+}
+
+fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|ext| ext.to_str()).unwrap_or_default() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css",
+        "js" => "text/javascript",
+        "json" => "application/json",
+        "xml" => "application/xml",
+        "txt" => "text/plain",
+        "pdf" => "application/pdf",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "svg" => "image/svg+xml",
+        "webp" => "image/webp",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn directory_crawl() {
+        let root = std::env::temp_dir().join(format!("sitemap_generator_directory_{}", std::process::id()));
+        let write = |path: &str, content: &str| {
+            let path = root.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        };
+        write("index.html", r#"<a href="posts">Posts</a> <a href="about">About</a> <a href="style.css">CSS</a>"#);
+        write("posts/index.html", r#"<a href="first/">First</a> <a href="missing.html">Missing</a>"#);
+        write("posts/first/index.html", r#"<a href="../../">Home</a>"#);
+        write("about.html", "About");
+        write("orphan.html", "Orphan");
+        write("style.css", "a { color: red; }");
+
+        let fetcher = DirectoryFetcher::new(&root, Url::parse("https://example.com/blog").unwrap()).set_pretty_urls(true);
+        let analysis = analyze_directory(fetcher, std::iter::empty(), Options::default()).await;
+        std::fs::remove_dir_all(&root).unwrap();
+        let analysis = analysis.unwrap();
+
+        let mut sites: Vec<&str> = analysis.sites.iter().map(|site| site.as_str()).collect();
+        sites.sort_unstable();
+        assert_eq!(sites, [
+            "https://example.com/blog/",
+            "https://example.com/blog/about",
+            "https://example.com/blog/orphan",
+            "https://example.com/blog/posts/",
+            "https://example.com/blog/posts/first/",
+            "https://example.com/blog/style.css",
+        ]);
+        assert_eq!(analysis.missing, [MissingFile {
+            url: Url::parse("https://example.com/blog/posts/missing.html").unwrap(),
+            referrer: Some(Url::parse("https://example.com/blog/posts/").unwrap()),
+        }]);
+        assert_eq!(analysis.pages[&Url::parse("https://example.com/blog/posts/missing.html").unwrap()].status, StatusCode::NOT_FOUND);
+        // The modification time of the files is used as Last-Modified
+        assert!(analysis.pages.values().filter(|page| page.status == StatusCode::OK).all(|page| page.last_modified.is_some()));
+    }
+
+    #[tokio::test]
+    async fn canonical_urls() {
+        let root = std::env::temp_dir().join(format!("sitemap_generator_canonical_{}", std::process::id()));
+        let write = |path: &str, content: &str| {
+            let path = root.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        };
+        write("index.html", r#"<a href="page.html">Page</a> <a href="docs/index.htm">Docs</a> <a href="old">Old</a>"#);
+        write("page.html", "Page");
+        write("docs/index.htm", "Docs");
+        write("old.htm", "Old");
+
+        let base_url = Url::parse("https://example.com/").unwrap();
+        let analyze = |pretty_urls| {
+            let fetcher = DirectoryFetcher::new(&root, base_url.clone()).set_pretty_urls(pretty_urls).set_crawl_all_files(false);
+            analyze_directory(fetcher, std::iter::once(base_url.clone()), Options::default())
+        };
+        let (plain, pretty) = (analyze(false).await, analyze(true).await);
+        std::fs::remove_dir_all(&root).unwrap();
+        let sites = |analysis: Result<DirectoryAnalysis>| {
+            let mut sites: Vec<String> = analysis.unwrap().sites.iter().map(|site| site.to_string()).collect();
+            sites.sort_unstable();
+            sites
+        };
+
+        assert_eq!(sites(plain), [
+            "https://example.com/",
+            "https://example.com/docs/",
+            "https://example.com/old",
+            "https://example.com/page.html",
+        ]);
+        assert_eq!(sites(pretty), [
+            "https://example.com/",
+            "https://example.com/docs/",
+            "https://example.com/old",
+            "https://example.com/page",
+        ]);
+    }
+}
@@ -0,0 +1,159 @@
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+
+use reqwest::StatusCode;
+use url::Url;
+
+/// An error which occurred analyzing a site.
+///
+/// Errors are never printed by the crawler: they are collected in the [`CrawlResult`](crate::CrawlResult)
+/// and forwarded to the observers and to the event stream.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum CrawlError {
+    /// The host name couldn't be resolved
+    Dns(Arc<anyhow::Error>),
+    /// The TLS handshake failed, e.g. because of an invalid certificate
+    Tls(Arc<anyhow::Error>),
+    /// The request took too long
+    Timeout,
+    /// Any other failure of the request, like a refused connection
+    Request(Arc<anyhow::Error>),
+    /// The site responded with a client or server error status
+    HttpStatus(StatusCode),
+    /// The site redirects to a site which isn't inside any site to analyze
+    OutOfScopeRedirect {
+        to: Url,
+    },
+    /// The site redirects too many times
+    TooManyRedirects,
+    /// The response couldn't be parsed, e.g. because of an invalid `Location` header
+    Parse(Arc<anyhow::Error>),
+    /// The body of the response is bigger than [`OptionsBuilder::set_max_body_size`](crate::OptionsBuilder::set_max_body_size)
+    BodyTooLarge {
+        limit: usize,
+    },
+}
+
+impl CrawlError {
+    /// Classifies an error returned by a [`Fetcher`](crate::Fetcher).
+    ///
+    /// Fetchers may return a `CrawlError` directly, like [`ReqwestFetcher`](crate::ReqwestFetcher) does,
+    /// otherwise the kind of error is found from the types of its causes.
+    pub fn from_fetch_error(error: anyhow::Error) -> CrawlError {
+        let error = match error.downcast::<CrawlError>() {
+            Ok(error) => return error,
+            Err(error) => error,
+        };
+
+        let timed_out = error.chain().any(|cause| {
+            cause.downcast_ref::<reqwest::Error>().is_some_and(reqwest::Error::is_timeout)
+            || cause.downcast_ref::<std::io::Error>().is_some_and(|error| error.kind() == std::io::ErrorKind::TimedOut)
+        });
+        if timed_out {
+            CrawlError::Timeout
+        } else if error.chain().any(|cause| cause.is::<native_tls::Error>()) {
+            CrawlError::Tls(Arc::new(error))
+        } else {
+            CrawlError::Request(Arc::new(error))
+        }
+    }
+
+    /// Classifies an error of a request made by [`ReqwestFetcher`](crate::ReqwestFetcher).
+    /// Connection failures are reported as [`Dns`](CrawlError::Dns) errors if the host cannot be resolved.
+    pub(crate) async fn from_reqwest_error(error: reqwest::Error) -> CrawlError {
+        if error.is_connect() {
+            let unresolved = match error.url().and_then(|url| Some((url.host_str()?.to_string(), url.port_or_known_default()?))) {
+                Some(address) => tokio::net::lookup_host(address).await.is_err(),
+                None => false,
+            };
+            if unresolved {
+                return CrawlError::Dns(Arc::new(error.into()));
+            }
+        }
+        CrawlError::from_fetch_error(error.into())
+    }
+}
+
+impl Display for CrawlError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CrawlError::Dns(error) => write!(f, "DNS error: {:#}", error),
+            CrawlError::Tls(error) => write!(f, "TLS error: {:#}", error),
+            CrawlError::Timeout => write!(f, "the request timed out"),
+            CrawlError::Request(error) => write!(f, "request failed: {:#}", error),
+            CrawlError::HttpStatus(status) => write!(f, "{}", status),
+            CrawlError::OutOfScopeRedirect { to } => write!(f, r#"redirecting to "{}", which is not to analyze"#, to),
+            CrawlError::TooManyRedirects => write!(f, "too many redirects"),
+            CrawlError::Parse(error) => write!(f, "invalid response: {:#}", error),
+            CrawlError::BodyTooLarge { limit } => write!(f, "the body is larger than {} bytes", limit),
+        }
+    }
+}
+
+impl std::error::Error for CrawlError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CrawlError::Dns(error) | CrawlError::Tls(error) | CrawlError::Request(error) | CrawlError::Parse(error) => {
+                Some(AsRef::<dyn std::error::Error>::as_ref(&**error))
+            },
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MemoryFetcher, Options};
+    use crate::test_utils::{crawl, url};
+
+    #[tokio::test]
+    async fn collected_errors() {
+        let fetcher = MemoryFetcher::new()
+        .with_page(url("/"), r#"<a href="/missing">Missing</a> <a href="/away">Away</a> <a href="/big">Big</a>"#)
+        .with_redirect(url("/away"), &url("https://other.com/"), StatusCode::FOUND)
+        .with_page(url("/big"), "a".repeat(1000));
+
+        let options = Options::builder().set_max_body_size(Some(500)).set_remove_error_pages(true).build();
+        let result = crawl(fetcher, options).await;
+
+        let mut errors: Vec<String> = result.errors.iter().map(|(url, error)| format!("{}: {}", url, error)).collect();
+        errors.sort();
+        assert_eq!(errors, [
+            r#"https://example.com/away: redirecting to "https://other.com/", which is not to analyze"#,
+            "https://example.com/big: the body is larger than 500 bytes",
+            "https://example.com/missing: 404 Not Found",
+        ]);
+    }
+
+    #[tokio::test]
+    async fn fetch_error_kinds() {
+        use std::time::Duration;
+
+        use crate::{Fetcher, ReqwestFetcher};
+
+        // Accepts the connections without ever answering
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let silent = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        tokio::spawn(async move {
+            let mut connections = Vec::new();
+            while let Ok((connection, _)) = listener.accept().await {
+                connections.push(connection);
+            }
+        });
+        let closed = {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap()
+        };
+
+        let fetcher = ReqwestFetcher::with_timeout(Duration::from_millis(100));
+        let error = |url: Url| {
+            let fetcher = fetcher.clone();
+            async move { CrawlError::from_fetch_error(fetcher.fetch(&url).await.err().unwrap()) }
+        };
+        assert!(matches!(error(silent).await, CrawlError::Timeout));
+        assert!(matches!(error(closed).await, CrawlError::Request(_)));
+        assert!(matches!(error(Url::parse("http://unresolvable.invalid/").unwrap()).await, CrawlError::Dns(_)));
+    }
+}
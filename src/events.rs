@@ -0,0 +1,85 @@
+use std::sync::Arc;
+
+use futures::{Stream, stream};
+use reqwest::StatusCode;
+use tokio::sync::mpsc;
+use url::Url;
+
+use crate::{crawl, CrawlError, Fetcher, Options, ReqwestFetcher, Validator};
+
+/// Max number of events which can be buffered before the crawl waits for them to be consumed
+const EVENT_BUFFER_SIZE: usize = 64;
+
+/// Events produced by the crawl, see [`analyze_stream`].
+#[derive(Debug)]
+pub enum CrawlEvent {
+    /// A page has been fetched, whatever its status
+    PageFetched {
+        url: Arc<Url>,
+        status: StatusCode,
+    },
+    /// A link to a site to analyze has been found in a page
+    LinkDiscovered {
+        from: Arc<Url>,
+        to: Arc<Url>,
+    },
+    /// An error occurred analyzing a site
+    Error {
+        url: Arc<Url>,
+        error: CrawlError,
+    },
+}
+
+/// Like [`analyze_stream_with_fetcher`], using the default [`ReqwestFetcher`].
+pub fn analyze_stream(sites_to_analyze: impl Iterator<Item=Url>, validator: Validator, options: Options) -> impl Stream<Item=CrawlEvent> {
+    analyze_stream_with_fetcher(ReqwestFetcher::new(), sites_to_analyze, validator, options)
+}
+
+/// Starts a crawl in the background, returning the stream of its events, which ends when the crawl does.
+///
+/// The crawl is slowed down when the events aren't consumed fast enough and it is stopped when the stream is dropped.
+/// Must be called inside a tokio runtime.
+pub fn analyze_stream_with_fetcher<F: Fetcher>(fetcher: F, sites_to_analyze: impl Iterator<Item=Url>, validator: Validator, options: Options) -> impl Stream<Item=CrawlEvent> {
+    let (tx, rx) = mpsc::channel(EVENT_BUFFER_SIZE);
+    let sites_to_analyze: Vec<Url> = sites_to_analyze.collect();
+
+    tokio::spawn(async move {
+        crawl(fetcher, sites_to_analyze.into_iter(), validator, options, Some(tx)).await;
+    });
+
+    stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|event| (event, rx))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+
+    use super::*;
+    use crate::MemoryFetcher;
+    use crate::test_utils::{BASE_URL, url};
+
+    #[tokio::test]
+    async fn stream_events() {
+        let fetcher = MemoryFetcher::new()
+        .with_page(url("/"), r#"<a href="/a">A</a> <a href="/missing">Missing</a>"#)
+        .with_page(url("/a"), "A");
+
+        let validator = Validator::new(std::iter::once(url(BASE_URL)));
+        let options = Options::builder().set_remove_error_pages(true).build();
+        let events: Vec<CrawlEvent> = analyze_stream_with_fetcher(fetcher, std::iter::once(url(BASE_URL)), validator, options).collect().await;
+
+        let mut fetched: Vec<&str> = events.iter().filter_map(|event| match event {
+            CrawlEvent::PageFetched { url, .. } => Some(url.as_str()),
+            _ => None,
+        }).collect();
+        fetched.sort_unstable();
+        assert_eq!(fetched, ["https://example.com/", "https://example.com/a", "https://example.com/missing"]);
+
+        let discovered = events.iter().filter(|event| matches!(event, CrawlEvent::LinkDiscovered { from, .. } if from.as_str() == "https://example.com/")).count();
+        assert_eq!(discovered, 2);
+
+        assert!(events.iter().any(|event| matches!(event, CrawlEvent::Error { url, .. } if url.as_str() == "https://example.com/missing")));
+    }
+}
@@ -0,0 +1,152 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::Arc;
+
+use anyhow::Result;
+use chrono::{DateTime, SecondsFormat, Utc};
+use quick_xml::events::{BytesDecl, BytesEnd, BytesStart, Event};
+use quick_xml::Writer;
+use url::Url;
+
+use crate::PageInfo;
+use crate::sitemap::write_element;
+use crate::utils::format_http_date;
+
+const ATOM_NAMESPACE: &str = "http://www.w3.org/2005/Atom";
+
+/// The number of pages of a feed when no other size is provided
+pub const DEFAULT_FEED_SIZE: usize = 20;
+
+/// A feed of the most recently modified pages, which can be written as Atom or RSS 2.0
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Feed {
+    pub title: String,
+    /// The site of the feed, also used as its id
+    pub link: Url,
+    /// The most recent first
+    pub entries: Vec<FeedEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeedEntry {
+    pub url: Url,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub updated: DateTime<Utc>,
+}
+
+impl Feed {
+    /// Creates a feed of the `size` pages modified most recently, using their titles and descriptions
+    /// (see [`OptionsBuilder::set_collect_titles`](crate::OptionsBuilder::set_collect_titles) and
+    /// [`OptionsBuilder::set_collect_descriptions`](crate::OptionsBuilder::set_collect_descriptions)).
+    /// Pages without a `Last-Modified` header, error pages and redirects are left out.
+    pub fn from_pages(title: String, link: Url, pages: &HashMap<Arc<Url>, PageInfo>, size: usize) -> Feed {
+        let mut entries: Vec<FeedEntry> = pages.iter()
+        .filter(|(_, page)| !page.is_error_or_redirect())
+        .filter_map(|(url, page)| Some(FeedEntry {
+            url: (**url).clone(),
+            title: page.title.clone(),
+            description: page.description.clone(),
+            updated: page.last_modified?,
+        }))
+        .collect();
+        // Pages modified at the same time are sorted by URL
+        entries.sort_by(|entry, other| other.updated.cmp(&entry.updated).then_with(|| entry.url.cmp(&other.url)));
+        entries.truncate(size);
+        Feed { title, link, entries }
+    }
+
+    /// The date of the most recent entry, or the current date if the feed is empty
+    pub fn updated(&self) -> DateTime<Utc> {
+        self.entries.iter().map(|entry| entry.updated).max().unwrap_or_else(Utc::now)
+    }
+
+    pub fn write_atom(&self, writer: impl Write) -> Result<()> {
+        let mut writer = Writer::new_with_indent(writer, b' ', 2);
+        writer.write_event(Event::Decl(BytesDecl::new(b"1.0", Some(b"UTF-8"), None)))?;
+        writer.write_event(Event::Start(BytesStart::borrowed_name(b"feed").with_attributes([("xmlns", ATOM_NAMESPACE)])))?;
+        write_element(&mut writer, b"title", Some(&self.title))?;
+        writer.write_event(Event::Empty(BytesStart::borrowed_name(b"link").with_attributes([("href", self.link.as_str())])))?;
+        write_element(&mut writer, b"id", Some(self.link.as_str()))?;
+        write_element(&mut writer, b"updated", Some(&self.updated().to_rfc3339_opts(SecondsFormat::Secs, true)))?;
+        for entry in &self.entries {
+            writer.write_event(Event::Start(BytesStart::borrowed_name(b"entry")))?;
+            // A title is required
+            write_element(&mut writer, b"title", Some(entry.title.as_deref().unwrap_or(entry.url.as_str())))?;
+            writer.write_event(Event::Empty(BytesStart::borrowed_name(b"link").with_attributes([("href", entry.url.as_str())])))?;
+            write_element(&mut writer, b"id", Some(entry.url.as_str()))?;
+            write_element(&mut writer, b"updated", Some(&entry.updated.to_rfc3339_opts(SecondsFormat::Secs, true)))?;
+            write_element(&mut writer, b"summary", entry.description.as_deref())?;
+            writer.write_event(Event::End(BytesEnd::borrowed(b"entry")))?;
+        }
+        writer.write_event(Event::End(BytesEnd::borrowed(b"feed")))?;
+        writer.inner().write_all(b"\n")?;
+        Ok(())
+    }
+
+    pub fn write_rss(&self, writer: impl Write) -> Result<()> {
+        let mut writer = Writer::new_with_indent(writer, b' ', 2);
+        writer.write_event(Event::Decl(BytesDecl::new(b"1.0", Some(b"UTF-8"), None)))?;
+        writer.write_event(Event::Start(BytesStart::borrowed_name(b"rss").with_attributes([("version", "2.0")])))?;
+        writer.write_event(Event::Start(BytesStart::borrowed_name(b"channel")))?;
+        write_element(&mut writer, b"title", Some(&self.title))?;
+        write_element(&mut writer, b"link", Some(self.link.as_str()))?;
+        // A description is required
+        write_element(&mut writer, b"description", Some(&self.title))?;
+        write_element(&mut writer, b"lastBuildDate", Some(&format_http_date(&self.updated())))?;
+        for entry in &self.entries {
+            writer.write_event(Event::Start(BytesStart::borrowed_name(b"item")))?;
+            write_element(&mut writer, b"title", Some(entry.title.as_deref().unwrap_or(entry.url.as_str())))?;
+            write_element(&mut writer, b"link", Some(entry.url.as_str()))?;
+            write_element(&mut writer, b"description", entry.description.as_deref())?;
+            write_element(&mut writer, b"guid", Some(entry.url.as_str()))?;
+            write_element(&mut writer, b"pubDate", Some(&format_http_date(&entry.updated)))?;
+            writer.write_event(Event::End(BytesEnd::borrowed(b"item")))?;
+        }
+        writer.write_event(Event::End(BytesEnd::borrowed(b"channel")))?;
+        writer.write_event(Event::End(BytesEnd::borrowed(b"rss")))?;
+        writer.inner().write_all(b"\n")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::header::LAST_MODIFIED;
+
+    use super::*;
+    use crate::{MemoryFetcher, Options};
+    use crate::test_utils::{crawl, url, with_headers};
+
+    #[tokio::test]
+    async fn feeds() {
+        let fetcher = MemoryFetcher::new()
+        .with_page(url("/"), r#"<a href="/old">Old</a> <a href="/new">New</a> <a href="/same">Same</a>"#);
+        let fetcher = with_headers(fetcher, "/old", &[(LAST_MODIFIED, "Mon, 02 May 2022 10:00:00 GMT")], "<title>Old</title>");
+        let fetcher = with_headers(fetcher, "/new", &[(LAST_MODIFIED, "Wed, 04 May 2022 10:00:00 GMT")], r#"<title>New &amp; shiny</title><meta name="description" content=" The  newest page ">"#);
+        let fetcher = with_headers(fetcher, "/same", &[(LAST_MODIFIED, "Wed, 04 May 2022 10:00:00 GMT")], "");
+
+        let options = Options::builder().set_collect_titles(true).set_collect_descriptions(true).build();
+        let result = crawl(fetcher, options).await;
+
+        let feed = Feed::from_pages("Example".to_string(), url("/"), &result.pages, 2);
+        let urls: Vec<&str> = feed.entries.iter().map(|entry| entry.url.as_str()).collect();
+        assert_eq!(urls, ["https://example.com/new", "https://example.com/same"]);
+        assert_eq!(feed.entries[0].description.as_deref(), Some("The newest page"));
+
+        let mut atom = Vec::new();
+        feed.write_atom(&mut atom).unwrap();
+        let atom = String::from_utf8(atom).unwrap();
+        assert!(atom.contains("<updated>2022-05-04T10:00:00Z</updated>"));
+        assert!(atom.contains("<title>New &amp; shiny</title>"));
+        assert!(atom.contains("<summary>The newest page</summary>"));
+        assert!(atom.contains("<title>https://example.com/same</title>"));
+
+        let mut rss = Vec::new();
+        feed.write_rss(&mut rss).unwrap();
+        let rss = String::from_utf8(rss).unwrap();
+        assert!(rss.contains("<pubDate>Wed, 04 May 2022 10:00:00 GMT</pubDate>"));
+        assert!(rss.contains("<guid>https://example.com/new</guid>"));
+        assert!(!rss.contains("https://example.com/old"));
+    }
+}
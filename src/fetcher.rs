@@ -0,0 +1,307 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use futures::{stream, StreamExt, TryStreamExt};
+use futures::stream::BoxStream;
+use reqwest::{Client, ClientBuilder, RequestBuilder, StatusCode};
+use reqwest::header::{CONTENT_TYPE, ETAG, HeaderMap, HeaderValue, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, LOCATION};
+use reqwest::redirect::Policy;
+use url::Url;
+
+use crate::{CrawlError, Link};
+use crate::utils::{format_http_date, parse_http_date};
+
+const APP_USER_AGENT: &str = concat!(
+env!("CARGO_PKG_NAME"),
+"/",
+env!("CARGO_PKG_VERSION"),
+);
+
+/// The body of a [`FetchResponse`], yielded in chunks as they are received.
+pub type Body = BoxStream<'static, Result<Bytes>>;
+
+/// Transport used by the crawler to retrieve pages.
+///
+/// A fetcher performs a single request and must not follow redirects by itself:
+/// redirects are handled by the crawler, which needs to keep track of every visited URL.
+pub trait Fetcher: Send + Sync + 'static {
+    fn fetch(&self, url: &Url) -> impl Future<Output=Result<FetchResponse>> + Send;
+
+    /// Like [`fetch`](Fetcher::fetch), but the response may be `304 Not Modified` without a body
+    /// if the page hasn't changed according to `conditions`. By default the conditions are ignored.
+    fn fetch_conditional(&self, url: &Url, conditions: &RequestConditions) -> impl Future<Output=Result<FetchResponse>> + Send {
+        let _ = conditions;
+        self.fetch(url)
+    }
+}
+
+impl<F: Fetcher> Fetcher for Arc<F> {
+    #[inline]
+    fn fetch(&self, url: &Url) -> impl Future<Output=Result<FetchResponse>> + Send {
+        (**self).fetch(url)
+    }
+
+    #[inline]
+    fn fetch_conditional(&self, url: &Url, conditions: &RequestConditions) -> impl Future<Output=Result<FetchResponse>> + Send {
+        (**self).fetch_conditional(url, conditions)
+    }
+}
+
+/// What is known about a page from a previous crawl, sent as `If-Modified-Since` and `If-None-Match`
+/// by [`Fetcher::fetch_conditional`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RequestConditions {
+    pub last_modified: Option<DateTime<Utc>>,
+    pub etag: Option<String>,
+    /// The links of the page, which are followed if it hasn't changed
+    pub links: Vec<Link>,
+}
+
+pub struct FetchResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    /// The URL of the response, which may differ from the requested one
+    pub url: Url,
+    pub body: Body,
+}
+
+impl FetchResponse {
+    /// Reads the whole body.
+    pub async fn bytes(self) -> Result<Vec<u8>> {
+        self.body.try_fold(Vec::new(), |mut vec, chunk| async move {
+            vec.extend_from_slice(&chunk);
+            Ok(vec)
+        }).await
+    }
+
+    /// Reads the whole body, replacing invalid UTF-8 sequences.
+    pub async fn text(self) -> Result<String> {
+        Ok(String::from_utf8_lossy(&self.bytes().await?).into_owned())
+    }
+}
+
+/// The default [`Fetcher`], backed by a [`reqwest::Client`].
+#[derive(Debug, Clone)]
+pub struct ReqwestFetcher {
+    client: Client,
+}
+
+impl ReqwestFetcher {
+    #[inline]
+    pub fn new() -> ReqwestFetcher {
+        ReqwestFetcher::with_client(ReqwestFetcher::client_builder().build().unwrap())
+    }
+
+    /// Fails the requests which take longer than `timeout`, including the download of the body,
+    /// with [`CrawlError::Timeout`]. By default requests never time out.
+    #[inline]
+    pub fn with_timeout(timeout: Duration) -> ReqwestFetcher {
+        ReqwestFetcher::with_client(ReqwestFetcher::client_builder().timeout(timeout).build().unwrap())
+    }
+
+    /// Wraps an already configured client. Note that the client's redirect policy should be [`Policy::none`].
+    #[inline]
+    pub fn with_client(client: Client) -> ReqwestFetcher {
+        ReqwestFetcher { client }
+    }
+}
+
+impl Default for ReqwestFetcher {
+    #[inline]
+    fn default() -> Self {
+        ReqwestFetcher::new()
+    }
+}
+
+impl ReqwestFetcher {
+    fn client_builder() -> ClientBuilder {
+        Client::builder()
+        .user_agent(APP_USER_AGENT)
+        .pool_idle_timeout(Some(Duration::from_secs(2))) // See https://github.com/hyperium/hyper/issues/2136#issuecomment-589488526
+        .redirect(Policy::none())
+    }
+
+    async fn send(request: RequestBuilder) -> Result<FetchResponse> {
+        let response = match request.send().await {
+            Ok(response) => response,
+            Err(err) => return Err(CrawlError::from_reqwest_error(err).await.into()),
+        };
+        Ok(FetchResponse {
+            status: response.status(),
+            headers: response.headers().clone(),
+            url: response.url().clone(),
+            body: response.bytes_stream().map_err(anyhow::Error::from).boxed(),
+        })
+    }
+}
+
+impl Fetcher for ReqwestFetcher {
+    async fn fetch(&self, url: &Url) -> Result<FetchResponse> {
+        ReqwestFetcher::send(self.client.get(url.clone())).await
+    }
+
+    async fn fetch_conditional(&self, url: &Url, conditions: &RequestConditions) -> Result<FetchResponse> {
+        let mut request = self.client.get(url.clone());
+        if let Some(last_modified) = &conditions.last_modified {
+            request = request.header(IF_MODIFIED_SINCE, format_http_date(last_modified));
+        }
+        if let Some(etag) = &conditions.etag {
+            request = request.header(IF_NONE_MATCH, etag.as_str());
+        }
+        ReqwestFetcher::send(request).await
+    }
+}
+
+/// A [`Fetcher`] serving responses from memory, useful for tests and offline benchmarks.
+///
+/// Requests to unknown URLs are answered with `404 Not Found`.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryFetcher {
+    responses: HashMap<Url, MemoryResponse>,
+}
+
+#[derive(Debug, Clone)]
+struct MemoryResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+impl MemoryFetcher {
+    #[inline]
+    pub fn new() -> MemoryFetcher {
+        Default::default()
+    }
+
+    /// Serves `html` with status `200 OK` at `url`.
+    pub fn with_page(self, url: Url, html: impl Into<Bytes>) -> MemoryFetcher {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("text/html; charset=utf-8"));
+        self.with_response(url, StatusCode::OK, headers, html)
+    }
+
+    /// Redirects `from` to `to` using the provided status.
+    pub fn with_redirect(self, from: Url, to: &Url, status: StatusCode) -> MemoryFetcher {
+        let mut headers = HeaderMap::new();
+        headers.insert(LOCATION, HeaderValue::from_str(to.as_str()).expect("URLs are valid header values"));
+        self.with_response(from, status, headers, Bytes::new())
+    }
+
+    pub fn with_response(mut self, url: Url, status: StatusCode, headers: HeaderMap, body: impl Into<Bytes>) -> MemoryFetcher {
+        self.responses.insert(url, MemoryResponse {
+            status,
+            headers,
+            body: body.into(),
+        });
+        self
+    }
+}
+
+impl Fetcher for MemoryFetcher {
+    async fn fetch(&self, url: &Url) -> Result<FetchResponse> {
+        let (status, headers, body) = match self.responses.get(url) {
+            Some(response) => (response.status, response.headers.clone(), response.body.clone()),
+            None => (StatusCode::NOT_FOUND, HeaderMap::new(), Bytes::new()),
+        };
+        Ok(FetchResponse {
+            status,
+            headers,
+            url: url.clone(),
+            body: stream::once(async move { Ok(body) }).boxed(),
+        })
+    }
+
+    /// Answers `304 Not Modified` if the `ETag` of the response matches, or else if its `Last-Modified` isn't more recent
+    async fn fetch_conditional(&self, url: &Url, conditions: &RequestConditions) -> Result<FetchResponse> {
+        let Some(response) = self.responses.get(url) else { return self.fetch(url).await };
+        let header = |name| response.headers.get(name).and_then(|value: &HeaderValue| value.to_str().ok());
+        let not_modified = match &conditions.etag {
+            Some(etag) => header(ETAG) == Some(etag.as_str()),
+            None => conditions.last_modified.zip(header(LAST_MODIFIED).and_then(parse_http_date))
+            .is_some_and(|(since, last_modified)| last_modified <= since),
+        };
+        if !not_modified {
+            return self.fetch(url).await;
+        }
+        let headers: HeaderMap = response.headers.iter()
+        .filter(|(name, _)| **name == ETAG || **name == LAST_MODIFIED)
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect();
+        Ok(FetchResponse {
+            status: StatusCode::NOT_MODIFIED,
+            headers,
+            url: url.clone(),
+            body: stream::empty().boxed(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Options;
+    use crate::test_utils::{crawl, sorted_sites, url, with_headers};
+
+    #[tokio::test]
+    async fn memory_crawl() {
+        let fetcher = MemoryFetcher::new()
+        .with_page(url("/"), r#"<a href="/a">A</a> <a href="https://other.com/">Other</a>"#)
+        .with_page(url("/a"), r#"<a href="b">B</a> <a href="/">Home</a>"#)
+        .with_redirect(url("/b"), &url("/c"), StatusCode::FOUND)
+        .with_page(url("/c"), "No links here");
+
+        let result = crawl(fetcher, Options::default()).await;
+        assert_eq!(sorted_sites(&result), ["https://example.com/", "https://example.com/a", "https://example.com/b", "https://example.com/c"]);
+    }
+
+    /// The sources of permanent redirects are replaced by their targets, since sitemaps must only list final URLs
+    #[tokio::test]
+    async fn memory_permanent_redirect() {
+        let fetcher = MemoryFetcher::new()
+        .with_redirect(url("/"), &url("/home"), StatusCode::MOVED_PERMANENTLY)
+        .with_page(url("/home"), r#"<a href="/outside">Outside</a> <a href="/old">Old</a>"#)
+        .with_redirect(url("/outside"), &url("https://other.com/"), StatusCode::FOUND)
+        .with_redirect(url("/old"), &url("/new"), StatusCode::PERMANENT_REDIRECT)
+        .with_page(url("/new"), "New");
+
+        let result = crawl(fetcher, Options::default()).await;
+        assert_eq!(sorted_sites(&result), ["https://example.com/home", "https://example.com/new", "https://example.com/outside"]);
+    }
+
+    #[tokio::test]
+    async fn memory_conditional_crawl() {
+        let fetcher = MemoryFetcher::new();
+        let fetcher = with_headers(fetcher, "/", &[(ETAG, r#""v2""#)], r#"<a href="/a">A</a> <a href="/b">B</a> <a href="/gone">Gone</a>"#);
+        let fetcher = with_headers(fetcher, "/a", &[(LAST_MODIFIED, "Mon, 02 May 2022 10:00:00 GMT")], r#"<a href="/c">C</a>"#);
+        let fetcher = with_headers(fetcher, "/b", &[(LAST_MODIFIED, "Wed, 04 May 2022 10:00:00 GMT")], "B");
+        let fetcher = fetcher.with_page(url("/c"), "C");
+
+        let since = parse_http_date("Mon, 02 May 2022 10:00:00 GMT");
+        let link = |path: &str| Link { url: url(path), anchor_text: None, element: "a".to_string() };
+        let known_pages = HashMap::from([
+            (url("/"), RequestConditions { last_modified: None, etag: Some(r#""v1""#.to_string()), links: Vec::new() }),
+            (url("/a"), RequestConditions { last_modified: since, etag: None, links: vec![link("/c")] }),
+            (url("/b"), RequestConditions { last_modified: since, etag: None, links: Vec::new() }),
+            (url("/gone"), RequestConditions { last_modified: since, etag: None, links: Vec::new() }),
+        ]);
+        let options = Options::builder().set_known_pages(known_pages).set_remove_error_pages(true).set_collect_links(true).build();
+        let result = crawl(fetcher, options).await;
+
+        let status = |path: &str| result.pages[&url(path)].status;
+        assert_eq!(status("/"), StatusCode::OK);
+        assert_eq!(result.pages[&url("/")].etag.as_deref(), Some(r#""v2""#));
+        assert_eq!(status("/a"), StatusCode::NOT_MODIFIED);
+        assert_eq!(result.pages[&url("/a")].last_modified, since);
+        assert_eq!(status("/b"), StatusCode::OK);
+        // The links of unchanged pages are the ones of the previous crawl
+        assert_eq!(result.pages[&url("/a")].links, [link("/c")]);
+        assert_eq!(result.pages[&url("/c")].depth, 2);
+        // Known pages which now respond with an error are removed
+        assert_eq!(sorted_sites(&result), ["https://example.com/", "https://example.com/a", "https://example.com/b", "https://example.com/c"]);
+    }
+}
@@ -0,0 +1,211 @@
+use std::collections::{BTreeSet, HashMap};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::Result;
+use quick_xml::events::{BytesDecl, BytesEnd, BytesStart, BytesText, Event};
+use quick_xml::Writer;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::PageInfo;
+
+const GRAPHML_NAMESPACE: &str = "http://graphml.graphdrawing.org/xmlns";
+
+/// A link found in a page, see [`PageInfo::links`]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Link {
+    pub url: Url,
+    /// The text of the link, or the `alt` of its images, or its `title`
+    pub anchor_text: Option<String>,
+    /// The name of the element of the link, `a` or `area`
+    pub element: String,
+}
+
+/// A link from the page `from` to `to`
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub struct Edge {
+    pub from: Url,
+    pub to: Url,
+    pub anchor_text: Option<String>,
+    pub element: String,
+}
+
+/// The file formats of [`LinkGraph::write`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphFormat {
+    /// Graphviz DOT
+    Dot,
+    GraphMl,
+    /// A JSON array of edges
+    Json,
+}
+
+impl GraphFormat {
+    /// Guesses the format from the extension of `path`: `.dot` or `.gv`, `.graphml`, `.json`
+    pub fn from_path(path: impl AsRef<Path>) -> Option<GraphFormat> {
+        let extension = path.as_ref().extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "dot" | "gv" => Some(GraphFormat::Dot),
+            "graphml" => Some(GraphFormat::GraphMl),
+            "json" => Some(GraphFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+/// The directed graph of the links between the pages. Edges are sorted and distinct
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(transparent)]
+pub struct LinkGraph {
+    pub edges: Vec<Edge>,
+}
+
+impl LinkGraph {
+    /// Creates the graph from the links collected by the crawl
+    /// (see [`OptionsBuilder::set_collect_links`](crate::OptionsBuilder::set_collect_links))
+    pub fn from_pages(pages: &HashMap<Arc<Url>, PageInfo>) -> LinkGraph {
+        let mut edges: Vec<Edge> = pages.iter()
+        .flat_map(|(url, page)| page.links.iter().map(move |link| Edge {
+            from: (**url).clone(),
+            to: link.url.clone(),
+            anchor_text: link.anchor_text.clone(),
+            element: link.element.clone(),
+        }))
+        .collect();
+        edges.sort();
+        edges.dedup();
+        LinkGraph { edges }
+    }
+
+    /// The pages which are the source or the target of an edge, sorted
+    pub fn nodes(&self) -> BTreeSet<&Url> {
+        self.edges.iter().flat_map(|edge| [&edge.from, &edge.to]).collect()
+    }
+
+    pub fn write(&self, format: GraphFormat, writer: impl Write) -> Result<()> {
+        match format {
+            GraphFormat::Dot => self.write_dot(writer),
+            GraphFormat::GraphMl => self.write_graphml(writer),
+            GraphFormat::Json => self.write_json(writer),
+        }
+    }
+
+    pub fn write_dot(&self, mut writer: impl Write) -> Result<()> {
+        writeln!(writer, "digraph links {{")?;
+        for node in self.nodes() {
+            writeln!(writer, "  {};", dot_string(node.as_str()))?;
+        }
+        for edge in &self.edges {
+            write!(writer, "  {} -> {} [element={}", dot_string(edge.from.as_str()), dot_string(edge.to.as_str()), dot_string(&edge.element))?;
+            if let Some(anchor_text) = &edge.anchor_text {
+                write!(writer, ", label={}", dot_string(anchor_text))?;
+            }
+            writeln!(writer, "];")?;
+        }
+        writeln!(writer, "}}")?;
+        Ok(())
+    }
+
+    pub fn write_graphml(&self, writer: impl Write) -> Result<()> {
+        let mut writer = Writer::new_with_indent(writer, b' ', 2);
+        writer.write_event(Event::Decl(BytesDecl::new(b"1.0", Some(b"UTF-8"), None)))?;
+        writer.write_event(Event::Start(BytesStart::borrowed_name(b"graphml").with_attributes([("xmlns", GRAPHML_NAMESPACE)])))?;
+        for key in ["anchor_text", "element"] {
+            writer.write_event(Event::Empty(BytesStart::borrowed_name(b"key").with_attributes([
+                ("id", key),
+                ("for", "edge"),
+                ("attr.name", key),
+                ("attr.type", "string"),
+            ])))?;
+        }
+        writer.write_event(Event::Start(BytesStart::borrowed_name(b"graph").with_attributes([("edgedefault", "directed")])))?;
+        for node in self.nodes() {
+            writer.write_event(Event::Empty(BytesStart::borrowed_name(b"node").with_attributes([("id", node.as_str())])))?;
+        }
+        for edge in &self.edges {
+            writer.write_event(Event::Start(BytesStart::borrowed_name(b"edge").with_attributes([
+                ("source", edge.from.as_str()),
+                ("target", edge.to.as_str()),
+            ])))?;
+            for (key, value) in [("anchor_text", edge.anchor_text.as_deref()), ("element", Some(edge.element.as_str()))] {
+                if let Some(value) = value {
+                    writer.write_event(Event::Start(BytesStart::borrowed_name(b"data").with_attributes([("key", key)])))?;
+                    writer.write_event(Event::Text(BytesText::from_plain_str(value)))?;
+                    writer.write_event(Event::End(BytesEnd::borrowed(b"data")))?;
+                }
+            }
+            writer.write_event(Event::End(BytesEnd::borrowed(b"edge")))?;
+        }
+        writer.write_event(Event::End(BytesEnd::borrowed(b"graph")))?;
+        writer.write_event(Event::End(BytesEnd::borrowed(b"graphml")))?;
+        writer.inner().write_all(b"\n")?;
+        Ok(())
+    }
+
+    pub fn write_json(&self, mut writer: impl Write) -> Result<()> {
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        writeln!(writer)?;
+        Ok(())
+    }
+}
+
+/// Quotes `str` as a DOT identifier
+fn dot_string(str: &str) -> String {
+    format!(r#""{}""#, str.replace('\\', r"\\").replace('"', r#"\""#).replace('\n', r"\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MemoryFetcher, Options};
+    use crate::test_utils::{crawl, url};
+
+    #[tokio::test]
+    async fn link_graph() {
+        let fetcher = MemoryFetcher::new()
+        .with_page(url("/"), r#"
+        <a href="/about">About   "us"</a>
+        <a href="/about"><img src="logo.png" alt="Logo"></a>
+        <map><area href="/map" alt="Map"></map>
+        <a href="https://other.com/">Other</a>
+    "#)
+        .with_page(url("/about"), r#"<a href="/" title="Home"></a>"#)
+        .with_page(url("/map"), "Map");
+        let options = Options::builder().set_collect_links(true).build();
+        let result = crawl(fetcher, options).await;
+
+        let graph = LinkGraph::from_pages(&result.pages);
+        let edge = |from: &str, to: &str, anchor_text: &str, element: &str| Edge {
+            from: url(from),
+            to: url(to),
+            anchor_text: Some(anchor_text.to_string()),
+            element: element.to_string(),
+        };
+        assert_eq!(graph.edges, [
+            edge("https://example.com/", "https://example.com/about", "About \"us\"", "a"),
+            edge("https://example.com/", "https://example.com/about", "Logo", "a"),
+            edge("https://example.com/", "https://example.com/map", "Map", "area"),
+            edge("https://example.com/about", "https://example.com/", "Home", "a"),
+        ]);
+        assert_eq!(graph.nodes().len(), 3);
+
+        let mut dot = Vec::new();
+        graph.write_dot(&mut dot).unwrap();
+        let dot = String::from_utf8(dot).unwrap();
+        assert!(dot.contains(r#"  "https://example.com/" -> "https://example.com/about" [element="a", label="About \"us\""];"#));
+
+        let mut graphml = Vec::new();
+        graph.write_graphml(&mut graphml).unwrap();
+        let graphml = String::from_utf8(graphml).unwrap();
+        assert!(graphml.contains(r#"<edge source="https://example.com/" target="https://example.com/map">"#));
+        assert!(graphml.contains(r#"<data key="anchor_text">About &quot;us&quot;</data>"#));
+
+        let mut json = Vec::new();
+        graph.write_json(&mut json).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(json[2]["element"], "area");
+        assert_eq!(json[3]["anchor_text"], "Home");
+    }
+}
@@ -0,0 +1,268 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::PageInfo;
+use crate::utils::{read_json, write_atomically};
+
+/// The damping factor of [`page_rank`] suggested by the original paper
+pub const DEFAULT_DAMPING: f64 = 0.85;
+/// Max number of `Last-Modified` values kept for every page by [`LastmodHistory`]
+pub const MAX_HISTORY_LENGTH: usize = 10;
+
+/// How frequently a page is likely to change, as defined by the sitemaps.org protocol
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Changefreq {
+    Always,
+    Hourly,
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+    Never,
+}
+
+impl Changefreq {
+    #[inline]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Changefreq::Always => "always",
+            Changefreq::Hourly => "hourly",
+            Changefreq::Daily => "daily",
+            Changefreq::Weekly => "weekly",
+            Changefreq::Monthly => "monthly",
+            Changefreq::Yearly => "yearly",
+            Changefreq::Never => "never",
+        }
+    }
+
+    /// The smallest frequency which covers a change every `interval`
+    pub fn from_interval(interval: Duration) -> Changefreq {
+        if interval < Duration::hours(1) {
+            Changefreq::Always
+        } else if interval < Duration::days(1) {
+            Changefreq::Hourly
+        } else if interval < Duration::weeks(1) {
+            Changefreq::Daily
+        } else if interval < Duration::days(31) {
+            Changefreq::Weekly
+        } else if interval < Duration::days(366) {
+            Changefreq::Monthly
+        } else {
+            Changefreq::Yearly
+        }
+    }
+}
+
+impl Display for Changefreq {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Changefreq {
+    type Err = String;
+
+    fn from_str(str: &str) -> Result<Self, Self::Err> {
+        Ok(match str {
+            "always" => Changefreq::Always,
+            "hourly" => Changefreq::Hourly,
+            "daily" => Changefreq::Daily,
+            "weekly" => Changefreq::Weekly,
+            "monthly" => Changefreq::Monthly,
+            "yearly" => Changefreq::Yearly,
+            "never" => Changefreq::Never,
+            _ => return Err(format!(r#"invalid changefreq "{}""#, str)),
+        })
+    }
+}
+
+/// Assigns a [`Changefreq`] to the pages whose path matches a pattern, like `/blog/*=daily`.
+/// `*` matches any sequence of characters, including `/`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangefreqRule {
+    pub pattern: String,
+    pub changefreq: Changefreq,
+}
+
+impl ChangefreqRule {
+    /// Whether the path of `url` matches the pattern
+    pub fn matches(&self, url: &Url) -> bool {
+        let mut parts = self.pattern.split('*');
+        let first = parts.next().unwrap_or_default();
+        let Some(mut rest) = url.path().strip_prefix(first) else { return false };
+        let parts: Vec<&str> = parts.collect();
+        let Some((last, middle)) = parts.split_last() else { return rest.is_empty() };
+        for part in middle {
+            match rest.find(part) {
+                Some(index) => rest = &rest[index + part.len()..],
+                None => return false,
+            }
+        }
+        rest.ends_with(last)
+    }
+
+    /// The frequency of the first rule matching `url`
+    pub fn find(rules: &[ChangefreqRule], url: &Url) -> Option<Changefreq> {
+        rules.iter().find(|rule| rule.matches(url)).map(|rule| rule.changefreq)
+    }
+}
+
+impl FromStr for ChangefreqRule {
+    type Err = String;
+
+    fn from_str(str: &str) -> Result<Self, Self::Err> {
+        let (pattern, changefreq) = str.rsplit_once('=').ok_or_else(|| format!(r#"invalid rule "{}", expected PATTERN=CHANGEFREQ"#, str))?;
+        Ok(ChangefreqRule {
+            pattern: pattern.to_string(),
+            changefreq: changefreq.parse()?,
+        })
+    }
+}
+
+/// The `Last-Modified` values observed for the pages over several crawls, used to guess how often they change.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct LastmodHistory {
+    /// The distinct values of every page, sorted
+    pub pages: BTreeMap<Url, Vec<DateTime<Utc>>>,
+}
+
+impl LastmodHistory {
+    #[inline]
+    pub fn load(path: impl AsRef<Path>) -> Result<LastmodHistory> {
+        read_json(path.as_ref(), "history")
+    }
+
+    /// Writes the history as indented JSON, so that it can be inspected
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        write_atomically(path.as_ref(), &serde_json::to_vec_pretty(self)?)
+    }
+
+    /// Adds the `Last-Modified` values of a crawl, keeping the last [`MAX_HISTORY_LENGTH`] values of every page.
+    /// Error pages and redirects are skipped.
+    pub fn record(&mut self, pages: &HashMap<Arc<Url>, PageInfo>) {
+        for (url, page) in pages.iter().filter(|(_, page)| !page.is_error_or_redirect()) {
+            let Some(last_modified) = page.last_modified else { continue };
+            let history = self.pages.entry((**url).clone()).or_default();
+            if let Err(index) = history.binary_search(&last_modified) {
+                history.insert(index, last_modified);
+                if history.len() > MAX_HISTORY_LENGTH {
+                    history.remove(0);
+                }
+            }
+        }
+    }
+
+    /// Guesses the frequency from the average interval between the changes. At least two changes are needed
+    pub fn changefreq(&self, url: &Url) -> Option<Changefreq> {
+        let history = self.pages.get(url)?;
+        let (first, last) = (history.first()?, history.last()?);
+        let changes = i32::try_from(history.len() - 1).ok().filter(|changes| *changes > 0)?;
+        Some(Changefreq::from_interval((*last - *first) / changes))
+    }
+}
+
+/// Computes the PageRank of the pages, using the links collected by the crawl
+/// (see [`OptionsBuilder::set_collect_links`](crate::OptionsBuilder::set_collect_links)).
+/// Links to URLs which aren't pages are ignored, redirects included. The ranks sum up to 1.
+pub fn page_rank(pages: &HashMap<Arc<Url>, PageInfo>, damping: f64, iterations: usize) -> HashMap<Arc<Url>, f64> {
+    // Sorted so that the ranks are summed in the same order every time
+    let mut urls: Vec<&Arc<Url>> = pages.iter().filter(|(_, page)| page.redirect.is_none()).map(|(url, _)| url).collect();
+    urls.sort();
+    let count = urls.len();
+    if count == 0 {
+        return HashMap::new();
+    }
+    let indexes: HashMap<&Url, usize> = urls.iter().enumerate().map(|(index, url)| (&***url, index)).collect();
+    let outgoing: Vec<Vec<usize>> = urls.iter().map(|url| {
+        let mut targets: Vec<usize> = pages[*url].links.iter()
+        .filter_map(|link| indexes.get(&link.url).copied())
+        .filter(|target| urls[*target] != *url)
+        .collect();
+        targets.sort_unstable();
+        targets.dedup();
+        targets
+    }).collect();
+
+    let mut ranks = vec![1.0 / count as f64; count];
+    for _ in 0..iterations {
+        // Pages without links spread their rank over every page
+        let dangling: f64 = outgoing.iter().zip(&ranks).filter(|(targets, _)| targets.is_empty()).map(|(_, rank)| rank).sum();
+        let mut next = vec![(1.0 - damping + damping * dangling) / count as f64; count];
+        for (source, targets) in outgoing.iter().enumerate() {
+            for target in targets {
+                next[*target] += damping * ranks[source] / targets.len() as f64;
+            }
+        }
+        ranks = next;
+    }
+    urls.into_iter().cloned().zip(ranks).collect()
+}
+
+/// Computes a priority between 0.1 and 1.0 for every page, rounded to one decimal. Pages closer to the
+/// starting points and pages with a higher [`page_rank`] get a higher priority, each counting for half of it.
+/// Redirects have no priority.
+pub fn priorities(pages: &HashMap<Arc<Url>, PageInfo>) -> HashMap<Arc<Url>, f64> {
+    let ranks = page_rank(pages, DEFAULT_DAMPING, 50);
+    let max_rank = ranks.values().copied().fold(0.0, f64::max);
+    pages.iter().filter(|(_, page)| page.redirect.is_none()).map(|(url, page)| {
+        let depth_score = 1.0 / (1.0 + page.depth as f64);
+        let rank_score = if max_rank > 0.0 { ranks[url] / max_rank } else { 0.0 };
+        let priority = (depth_score + rank_score) / 2.0;
+        (url.clone(), ((priority * 10.0).round() / 10.0).clamp(0.1, 1.0))
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MemoryFetcher, Options};
+    use crate::test_utils::{crawl, url};
+
+    #[tokio::test]
+    async fn priority_and_changefreq() {
+        let fetcher = MemoryFetcher::new()
+        .with_page(url("/"), r#"<a href="/blog/">Blog</a> <a href="/about">About</a>"#)
+        .with_page(url("/blog/"), r#"<a href="/">Home</a> <a href="/blog/post">Post</a>"#)
+        .with_page(url("/blog/post"), r#"<a href="/">Home</a> <a href="/blog/">Blog</a>"#)
+        .with_page(url("/about"), r#"<a href="/">Home</a>"#);
+        let options = Options::builder().set_collect_links(true).build();
+        let result = crawl(fetcher, options).await;
+
+        assert_eq!(result.pages[&url("/blog/post")].depth, 2);
+        let ranks = page_rank(&result.pages, DEFAULT_DAMPING, 50);
+        assert!((ranks.values().sum::<f64>() - 1.0).abs() < 1e-9);
+        assert!(ranks[&url("/")] > ranks[&url("/blog/")]);
+        assert!(ranks[&url("/blog/")] > ranks[&url("/about")]);
+        let priorities = priorities(&result.pages);
+        assert_eq!(priorities[&url("/")], 1.0);
+        assert!(priorities[&url("/blog/post")] < priorities[&url("/blog/")]);
+
+        let rules: Vec<ChangefreqRule> = ["/blog/*=daily", "*.pdf=never", "/=hourly"].into_iter().map(|rule| rule.parse().unwrap()).collect();
+        assert_eq!(ChangefreqRule::find(&rules, &url("/blog/post")), Some(Changefreq::Daily));
+        assert_eq!(ChangefreqRule::find(&rules, &url("/files/guide.pdf")), Some(Changefreq::Never));
+        assert_eq!(ChangefreqRule::find(&rules, &url("/")), Some(Changefreq::Hourly));
+        assert_eq!(ChangefreqRule::find(&rules, &url("/about")), None);
+        assert!("/blog/*=sometimes".parse::<ChangefreqRule>().is_err());
+
+        let date = |str: &str| DateTime::parse_from_rfc3339(str).unwrap().with_timezone(&Utc);
+        let mut history = LastmodHistory::default();
+        for last_modified in ["2022-05-01T00:00:00Z", "2022-05-01T00:00:00Z", "2022-05-03T00:00:00Z", "2022-05-05T00:00:00Z"] {
+            let mut pages = result.pages.clone();
+            pages.get_mut(&url("/")).unwrap().last_modified = Some(date(last_modified));
+            history.record(&pages);
+        }
+        assert_eq!(history.pages[&url("/")].len(), 3);
+        assert_eq!(history.changefreq(&url("/")), Some(Changefreq::Daily));
+        assert_eq!(history.changefreq(&url("/about")), None);
+    }
+}
@@ -0,0 +1,254 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+
+use reqwest::StatusCode;
+use url::Url;
+
+use crate::{PageInfo, Validator};
+use crate::sitemap::Alternate;
+use crate::utils::{filter_http, normalize};
+
+/// A problem of the hreflang annotations, see [`hreflang_groups`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum HreflangIssue {
+    /// `from` declares `to` as an alternate, but `to` doesn't declare `from`
+    NotReciprocal {
+        from: Url,
+        to: Url,
+        hreflang: String,
+    },
+    /// `from` declares `to` as an alternate, but `to` isn't inside the sites to analyze
+    OutOfScope {
+        from: Url,
+        to: Url,
+        hreflang: String,
+    },
+    /// `from` declares `to` as an alternate, but `to` hasn't been analyzed, so its alternates aren't known
+    NotCrawled {
+        from: Url,
+        to: Url,
+        hreflang: String,
+    },
+}
+
+impl Display for HreflangIssue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HreflangIssue::NotReciprocal { from, to, hreflang } => write!(f, r#""{}" declares "{}" as its "{}" alternate, which doesn't link back"#, from, to, hreflang),
+            HreflangIssue::OutOfScope { from, to, hreflang } => write!(f, r#""{}" declares "{}" as its "{}" alternate, which is outside the sites to analyze"#, from, to, hreflang),
+            HreflangIssue::NotCrawled { from, to, hreflang } => write!(f, r#""{}" declares "{}" as its "{}" alternate, which hasn't been crawled so it's unknown whether it links back"#, from, to, hreflang),
+        }
+    }
+}
+
+/// The language variants of the pages, see [`hreflang_groups`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HreflangGroups {
+    /// The alternates of every page which is part of a group, including the page itself. Sorted by language
+    pub alternates: HashMap<Url, Vec<Alternate>>,
+    /// Sorted by page
+    pub issues: Vec<HreflangIssue>,
+}
+
+/// Groups the pages which are language variants of each other, using the alternates collected by the crawl
+/// (see [`OptionsBuilder::set_collect_alternates`](crate::OptionsBuilder::set_collect_alternates)).
+///
+/// Two pages are in the same group only if they declare each other as alternates. The other annotations are reported as issues.
+pub fn hreflang_groups(pages: &HashMap<Arc<Url>, PageInfo>, validator: &Validator) -> HreflangGroups {
+    let declares = |from: &Url, to: &Url| pages.get(from).is_some_and(|page| page.alternates.iter().any(|alternate| alternate.href == *to));
+    // The pages which haven't changed since a previous crawl aren't analyzed, see OptionsBuilder::set_known_pages
+    let crawled = |url: &Url| pages.get(url).is_some_and(|page| page.status != StatusCode::NOT_MODIFIED);
+
+    let mut issues = Vec::new();
+    let mut groups = Groups::default();
+    for (url, page) in pages {
+        for alternate in &page.alternates {
+            if alternate.href == **url {
+                continue;
+            }
+            if !validator.is_valid(&alternate.href) {
+                issues.push(HreflangIssue::OutOfScope {
+                    from: (**url).clone(),
+                    to: alternate.href.clone(),
+                    hreflang: alternate.hreflang.clone(),
+                });
+            } else if declares(&alternate.href, url) {
+                groups.union(url, &alternate.href);
+            } else if !crawled(&alternate.href) {
+                issues.push(HreflangIssue::NotCrawled {
+                    from: (**url).clone(),
+                    to: alternate.href.clone(),
+                    hreflang: alternate.hreflang.clone(),
+                });
+            } else {
+                issues.push(HreflangIssue::NotReciprocal {
+                    from: (**url).clone(),
+                    to: alternate.href.clone(),
+                    hreflang: alternate.hreflang.clone(),
+                });
+            }
+        }
+    }
+
+    // Every group lists the annotations of its pages pointing inside the group
+    let mut links: HashMap<Url, BTreeSet<Alternate>> = HashMap::new();
+    for (url, page) in pages {
+        let Some(root) = groups.find(url) else { continue };
+        for alternate in &page.alternates {
+            if groups.find(&alternate.href).as_ref() == Some(&root) {
+                links.entry(root.clone()).or_default().insert(alternate.clone());
+            }
+        }
+    }
+    let alternates = groups.members().into_iter()
+    .filter_map(|(url, root)| Some((url, links.get(&root)?.iter().cloned().collect())))
+    .collect();
+
+    issues.sort_by(|first, second| issue_key(first).cmp(&issue_key(second)));
+    HreflangGroups {
+        alternates,
+        issues,
+    }
+}
+
+/// Parses the alternates declared by `Link` headers, like `<https://example.com/de/>; rel="alternate"; hreflang="de"`
+pub fn parse_link_header(header: &str, base_url: &Url) -> Vec<Alternate> {
+    let mut alternates = Vec::new();
+    let mut rest = header;
+    while let Some(start) = rest.find('<') {
+        let Some(end) = rest[start..].find('>') else { break };
+        let target = &rest[start + 1..start + end];
+        rest = &rest[start + end + 1..];
+        // The parameters end where the next link starts
+        let params_end = rest.match_indices(',')
+        .map(|(comma, _)| comma)
+        .find(|comma| rest[comma + 1..].trim_start().starts_with('<'))
+        .unwrap_or(rest.len());
+        let params = &rest[..params_end];
+        rest = &rest[params_end..];
+
+        let mut rel = None;
+        let mut hreflang = None;
+        for param in params.split(';') {
+            let Some((name, value)) = param.split_once('=') else { continue };
+            let value = value.trim().trim_matches('"');
+            match name.trim().to_ascii_lowercase().as_str() {
+                "rel" => rel = Some(value),
+                "hreflang" => hreflang = Some(value),
+                _ => {},
+            }
+        }
+        let is_alternate = rel.is_some_and(|rel| rel.split_ascii_whitespace().any(|rel| rel.eq_ignore_ascii_case("alternate")));
+        if let (true, Some(hreflang), Ok(href)) = (is_alternate, hreflang, base_url.join(target.trim())) {
+            if filter_http(&href) {
+                alternates.push(Alternate {
+                    hreflang: hreflang.to_string(),
+                    href: normalize(href),
+                });
+            }
+        }
+    }
+    alternates
+}
+
+fn issue_key(issue: &HreflangIssue) -> (&Url, &Url) {
+    match issue {
+        HreflangIssue::NotReciprocal { from, to, .. } | HreflangIssue::OutOfScope { from, to, .. } | HreflangIssue::NotCrawled { from, to, .. } => (from, to),
+    }
+}
+
+/// A union-find of the pages
+#[derive(Default)]
+struct Groups {
+    parents: HashMap<Url, Url>,
+}
+
+impl Groups {
+    fn find(&self, url: &Url) -> Option<Url> {
+        let mut current = self.parents.get(url)?;
+        while let Some(parent) = self.parents.get(current).filter(|parent| *parent != current) {
+            current = parent;
+        }
+        Some(current.clone())
+    }
+
+    fn union(&mut self, first: &Url, second: &Url) {
+        for url in [first, second] {
+            self.parents.entry(url.clone()).or_insert_with(|| url.clone());
+        }
+        let (first, second) = (self.find(first).expect("inserted above"), self.find(second).expect("inserted above"));
+        if first != second {
+            // Keeps the smallest URL as the root, so that the result doesn't depend on the order of the pages
+            let (root, child) = if first < second { (first, second) } else { (second, first) };
+            self.parents.insert(child, root);
+        }
+    }
+
+    /// The pages with the root of their group
+    fn members(&self) -> Vec<(Url, Url)> {
+        self.parents.keys().map(|url| (url.clone(), self.find(url).expect("url is a key"))).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::header::{CONTENT_TYPE, HeaderMap, HeaderValue, LINK};
+
+    use super::*;
+    use crate::{analyze_with_fetcher, MemoryFetcher, Options};
+    use crate::test_utils::{BASE_URL, url};
+
+    #[tokio::test]
+    async fn hreflang_annotations() {
+        let links = r#"
+        <link rel="alternate" hreflang="en" href="/en/">
+        <link rel="alternate" hreflang="de" href="/de/">
+        <link rel="alternate" hreflang="x-default" href="/en/">
+        <link rel="alternate" hreflang="fr" href="https://other.com/fr/">
+        <a href="/de/">Deutsch</a> <a href="/it/">Italiano</a> <a href="/guide.pdf">Guide</a>
+    "#;
+        let mut pdf_headers = HeaderMap::new();
+        pdf_headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/pdf"));
+        pdf_headers.insert(LINK, HeaderValue::from_static(r#"<https://example.com/en/>; rel="alternate"; title="Guide, English"; hreflang="en", </style.css>; rel=preload"#));
+        let fetcher = MemoryFetcher::new()
+        .with_page(url("/en/"), links)
+        .with_page(url("/de/"), links)
+        .with_page(url("/it/"), r#"<link rel="alternate" hreflang="en" href="/en/"> <link rel="alternate" hreflang="es" href="/es/">"#)
+        .with_response(url("/guide.pdf"), StatusCode::OK, pdf_headers, "%PDF");
+
+        let options = Options::builder().set_collect_alternates(true).build();
+        let validator = Validator::new(std::iter::once(url(BASE_URL)));
+        let result = analyze_with_fetcher(fetcher, std::iter::once(url("/en/")), validator.clone(), options).await;
+        let groups = hreflang_groups(&result.pages, &validator);
+
+        let alternate = |hreflang: &str, href: &str| Alternate { hreflang: hreflang.to_string(), href: url(href) };
+        let expected = [alternate("de", "https://example.com/de/"), alternate("en", "https://example.com/en/"), alternate("x-default", "https://example.com/en/")];
+        assert_eq!(groups.alternates.len(), 2);
+        assert_eq!(groups.alternates[&url("/en/")], expected);
+        assert_eq!(groups.alternates[&url("/de/")], expected);
+
+        assert_eq!(groups.issues, [HreflangIssue::OutOfScope {
+            from: url("/de/"),
+            to: url("https://other.com/fr/"),
+            hreflang: "fr".to_string(),
+        }, HreflangIssue::OutOfScope {
+            from: url("/en/"),
+            to: url("https://other.com/fr/"),
+            hreflang: "fr".to_string(),
+        }, HreflangIssue::NotReciprocal {
+            from: url("/guide.pdf"),
+            to: url("/en/"),
+            hreflang: "en".to_string(),
+        }, HreflangIssue::NotReciprocal {
+            from: url("/it/"),
+            to: url("/en/"),
+            hreflang: "en".to_string(),
+        }, HreflangIssue::NotCrawled {
+            from: url("/it/"),
+            to: url("/es/"),
+            hreflang: "es".to_string(),
+        }]);
+    }
+}
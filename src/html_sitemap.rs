@@ -0,0 +1,167 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use anyhow::{anyhow, Result};
+use percent_encoding::percent_decode_str;
+use url::Url;
+
+/// Replaced by the tree of the pages in the templates of [`HtmlSitemap::write`]
+pub const TREE_PLACEHOLDER: &str = "{{tree}}";
+
+/// The template used when no other template is provided
+pub const DEFAULT_HTML_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Site map</title>
+</head>
+<body>
+<h1>Site map</h1>
+{{tree}}
+</body>
+</html>
+"#;
+
+/// A page for users listing the pages as a tree of nested lists, following the segments of their paths.
+/// Pages are labelled with their title, other segments with their name.
+/// Pages differing only by a trailing slash, like `/blog` and `/blog/`, are listed in the same item.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HtmlSitemap {
+    // The children of the root are the origins of the pages
+    root: Node,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Node {
+    /// The pages of the segment, sorted by URL: there are two if the path exists with and without a trailing slash
+    pages: Vec<(Url, Option<String>)>,
+    children: BTreeMap<String, Node>,
+}
+
+impl HtmlSitemap {
+    #[inline]
+    pub fn new() -> HtmlSitemap {
+        Default::default()
+    }
+
+    /// Adds a page with its title. The query is considered part of the last segment
+    pub fn insert(&mut self, url: Url, title: Option<String>) {
+        let mut segments: Vec<String> = url.path_segments()
+        .into_iter()
+        .flatten()
+        .filter(|segment| !segment.is_empty())
+        .map(|segment| percent_decode_str(segment).decode_utf8_lossy().into_owned())
+        .collect();
+        if let Some(query) = url.query() {
+            match segments.last_mut() {
+                Some(last) => *last = format!("{}?{}", last, query),
+                None => segments.push(format!("?{}", query)),
+            }
+        }
+
+        let mut node = self.root.children.entry(url.origin().ascii_serialization()).or_default();
+        for segment in segments {
+            node = node.children.entry(segment).or_default();
+        }
+        match node.pages.binary_search_by(|(page, _)| page.cmp(&url)) {
+            Ok(index) => node.pages[index].1 = title,
+            Err(index) => node.pages.insert(index, (url, title)),
+        }
+    }
+
+    /// Writes `template` replacing [`TREE_PLACEHOLDER`] with the tree of the pages
+    pub fn write(&self, template: &str, mut writer: impl Write) -> Result<()> {
+        let (before, after) = template.split_once(TREE_PLACEHOLDER).ok_or_else(|| anyhow!("The template doesn't contain {}", TREE_PLACEHOLDER))?;
+        writer.write_all(before.as_bytes())?;
+        write_list(&mut writer, &self.root.children, 0)?;
+        writer.write_all(after.trim_start_matches('\n').as_bytes())?;
+        Ok(())
+    }
+}
+
+impl FromIterator<(Url, Option<String>)> for HtmlSitemap {
+    fn from_iter<T: IntoIterator<Item=(Url, Option<String>)>>(iter: T) -> Self {
+        let mut sitemap = HtmlSitemap::new();
+        for (url, title) in iter {
+            sitemap.insert(url, title);
+        }
+        sitemap
+    }
+}
+
+fn write_list(writer: &mut impl Write, nodes: &BTreeMap<String, Node>, indent: usize) -> Result<()> {
+    let padding = " ".repeat(indent);
+    writeln!(writer, "{}<ul>", padding)?;
+    for (segment, node) in nodes {
+        write!(writer, "{}  <li>", padding)?;
+        if node.pages.is_empty() {
+            write!(writer, "{}", escape(segment))?;
+        }
+        for (index, (url, title)) in node.pages.iter().enumerate() {
+            if index > 0 {
+                write!(writer, ", ")?;
+            }
+            write!(writer, r#"<a href="{}">{}</a>"#, escape(url.as_str()), escape(title.as_deref().unwrap_or(segment)))?;
+        }
+        if !node.children.is_empty() {
+            writeln!(writer)?;
+            write_list(writer, &node.children, indent + 4)?;
+            write!(writer, "{}  ", padding)?;
+        }
+        writeln!(writer, "</li>")?;
+    }
+    writeln!(writer, "{}</ul>", padding)?;
+    Ok(())
+}
+
+fn escape(str: &str) -> String {
+    str.replace('&', "&amp;")
+    .replace('<', "&lt;")
+    .replace('>', "&gt;")
+    .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::url;
+
+    #[test]
+    fn html_sitemap() {
+        let sitemap: HtmlSitemap = [
+            (url("/blog/first-post"), Some("First <post>".to_string())),
+            (url("/"), Some("Home".to_string())),
+            (url("/blog/"), None),
+            (url("/blog"), Some("Blog".to_string())),
+            (url("/docs/caf%C3%A9/guide?page=2"), Some("Guide".to_string())),
+        ].into_iter().collect();
+
+        let mut html = Vec::new();
+        sitemap.write("<body>\n{{tree}}\n</body>\n", &mut html).unwrap();
+        assert_eq!(String::from_utf8(html).unwrap(), r#"<body>
+<ul>
+  <li><a href="https://example.com/">Home</a>
+    <ul>
+      <li><a href="https://example.com/blog">Blog</a>, <a href="https://example.com/blog/">blog</a>
+        <ul>
+          <li><a href="https://example.com/blog/first-post">First &lt;post&gt;</a></li>
+        </ul>
+      </li>
+      <li>docs
+        <ul>
+          <li>café
+            <ul>
+              <li><a href="https://example.com/docs/caf%C3%A9/guide?page=2">Guide</a></li>
+            </ul>
+          </li>
+        </ul>
+      </li>
+    </ul>
+  </li>
+</ul>
+</body>
+"#);
+
+        assert!(sitemap.write("<body></body>", Vec::new()).is_err());
+    }
+}
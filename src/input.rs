@@ -1,18 +1,104 @@
-use std::collections::HashSet;
-use std::fmt::format;
+use std::collections::{HashMap, HashSet};
+use std::io::IsTerminal;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
 
-use clap::{CommandFactory, ErrorKind, Parser};
+use clap::{Args, CommandFactory, ErrorKind, Parser, Subcommand};
+use indicatif::ProgressBar;
 use url::Url;
 
-use sitemap_generator::Options;
+use sitemap_generator::{CancellationHandle, ChangefreqRule, CrawlObserver, CrawlState, DiscoveredSites, GraphFormat, Options, parse_w3c_datetime, RequestConditions, SitemapEntry, SitemapKind, SortOrder, Validator};
+use sitemap_generator::html_sitemap::{DEFAULT_HTML_TEMPLATE, TREE_PLACEHOLDER};
 
+use crate::commands::{load_sitemap, load_update_cache};
+use crate::config::{Config, OutputFormat};
+use crate::printer::Printer;
+use crate::records::NdjsonWriter;
+use crate::progress::{progress_bar, Stats};
 use crate::utils::*;
 
 #[derive(Parser)]
-#[clap(author, version, about, long_about = None)]
+#[clap(author, version, about, long_about = None, args_conflicts_with_subcommands = true)]
 struct Input {
+    #[clap(subcommand)]
+    command: Option<Command>,
+    /// Without a subcommand, the sites are crawled
+    #[clap(flatten)]
+    crawl: CrawlArgs,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Crawl the sites and print their sitemap (default)
+    Crawl(CrawlArgs),
+    /// Check sitemap files against the sitemaps.org protocol
+    Validate {
+        /// Sitemap files to check
+        #[clap(required = true, parse(from_os_str))]
+        files: Vec<PathBuf>,
+        #[clap(long)]
+        /// The URL where the files are published, their URLs must be under it
+        base_url: Option<String>,
+    },
+    /// Compare a sitemap with another one or with a fresh crawl
+    Diff {
+        #[clap(long)]
+        /// The previous sitemap, either a file or a URL like https://example.com/sitemap.xml
+        old: SitemapSource,
+        #[clap(long)]
+        /// The sitemap to compare with, either a file or a URL. If not provided, the sites are crawled
+        new: Option<SitemapSource>,
+        #[clap(long)]
+        /// Print the differences in JSON format
+        json: bool,
+        #[clap(long)]
+        /// Exit with code 4 when more URLs than this are removed. Can be a number or a percentage of the old URLs, like 5%
+        max_removed: Option<RemovalThreshold>,
+        #[clap(flatten)]
+        crawl: CrawlArgs,
+    },
+    /// Crawl the sites and print the pages of a reference list which aren't reachable by links
+    Orphans {
+        #[clap(long)]
+        /// The pages which should be reachable: a file with one URL per line, a sitemap file or the URL of a sitemap.
+        /// These pages are not used as starting points
+        reference: SitemapSource,
+        #[clap(long)]
+        /// Request the orphan pages to check whether they still exist
+        check: bool,
+        #[clap(flatten)]
+        crawl: CrawlArgs,
+    },
+    /// Combine several sitemaps into a single one without duplicates
+    Merge {
+        /// Sitemap files to combine, either urlsets or sitemap indexes
+        #[clap(required = true, parse(from_os_str))]
+        files: Vec<PathBuf>,
+        #[clap(long, requires = "base-url")]
+        /// Write a sitemap index listing the files instead of combining their URLs
+        index: bool,
+        #[clap(long)]
+        /// The URL where the files are published, used with --index
+        base_url: Option<String>,
+        #[clap(short, long, parse(from_os_str))]
+        /// File to write the sitemap to, instead of stdout
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Args)]
+struct CrawlArgs {
     /// List of sites to analyze. Example: https://frengor.com
     sites_to_analyze: Vec<String>,
+    #[clap(long, parse(from_os_str))]
+    /// Config file to use, in TOML or YAML format. By default, "sitemap.toml" (or "sitemap.yaml") is loaded
+    /// from the current directory if present. Flags take precedence over the values of the file
+    config: Option<PathBuf>,
+    #[clap(long)]
+    /// Print the effective config in TOML format and exit
+    dump_config: bool,
     #[clap(short, long)]
     /// Sites to start the crawl from. Contains DOMAINS_TO_ANALYZE by default (add --sdasp to disable)
     starting_points: Option<Vec<String>>,
@@ -22,48 +108,564 @@ struct Input {
     #[clap(short, long)]
     /// List of links to add to the sitemap, but not to crawl
     additional_links: Option<Vec<String>>,
-    #[clap(short = 'c', long, default_value_t = num_cpus::get())]
+    #[clap(short = 'c', long)]
     /// Max number of sites analyzed simultaneously. Default value is the number of CPU cores
-    max_concurrent_tasks: usize,
+    max_concurrent_tasks: Option<usize>,
     #[clap(long = "rqaf")]
     /// Remove Query And Fragment from the analyzed urls
     remove_query_and_fragment: bool,
-    #[clap(short = 'd', long, default_value_t = 50)]
+    #[clap(short = 'd', long)]
     /// Max depth of the crawl. Default value is 50
-    max_depth: usize,
+    max_depth: Option<usize>,
+    #[clap(long, parse(from_os_str))]
+    /// Crawl the files of a local directory (e.g. a static site build) instead of making requests.
+    /// The only site to analyze is used as the public URL of the directory
+    directory: Option<PathBuf>,
+    #[clap(long)]
+    /// Map "page.html" files to the "page" URL when crawling a directory
+    pretty_urls: bool,
+    #[clap(long, parse(from_os_str))]
+    /// File where the progress of the crawl is periodically saved
+    state_file: Option<PathBuf>,
+    #[clap(long)]
+    /// Seconds between the saves of the state file. Default value is 30
+    checkpoint_interval: Option<u64>,
+    #[clap(long)]
+    /// Resume the crawl from the state file
+    resume: bool,
+    #[clap(long)]
+    /// Seconds to wait for the sites being analyzed when the crawl is interrupted. Default value is 10
+    grace_period: Option<u64>,
+    #[clap(long)]
+    /// Max size in bytes of the pages to analyze, bigger pages are reported as errors
+    max_body_size: Option<usize>,
+    #[clap(long)]
+    /// Seconds after which a request is reported as timed out, 0 to wait forever. Default value is 30
+    timeout: Option<u64>,
+    #[clap(long)]
+    /// Add the images of the pages to the XML sitemap
+    images: bool,
+    #[clap(long)]
+    /// Sites whose images are added even though they aren't analyzed, like a CDN. Example: https://cdn.frengor.com
+    image_sites: Option<Vec<String>>,
+    #[clap(long)]
+    /// Add the videos of the pages to the XML sitemap, from <video> elements, YouTube and Vimeo players and schema.org VideoObjects
+    videos: bool,
+    #[clap(long)]
+    /// Add the language variants declared with hreflang to the XML sitemap, reporting the ones which aren't reciprocal
+    hreflang: bool,
+    #[clap(long)]
+    /// Add a priority to the XML sitemap, computed from the depth of the pages and the links pointing to them
+    priority: bool,
+    #[clap(long = "changefreq-rule")]
+    /// Add a changefreq to the pages whose path matches a pattern. Example: "/blog/*=daily"
+    changefreq_rules: Option<Vec<String>>,
+    #[clap(long, parse(from_os_str))]
+    /// File keeping the Last-Modified values of previous crawls, used to add a changefreq to the pages which change regularly
+    lastmod_history: Option<PathBuf>,
+    #[clap(long, parse(from_os_str))]
+    /// File to write the graph of the links between the pages to, as Graphviz DOT (.dot or .gv), GraphML (.graphml) or JSON (.json)
+    graph: Option<PathBuf>,
+    #[clap(long, arg_enum)]
+    /// Format of the sitemap. Default value is plain
+    format: Option<OutputFormat>,
+    #[clap(long)]
+    /// Order of the pages in the sitemap: lexicographic, depth (closest to the starting points first) or discovery
+    /// (breadth-first order of the links). Default value is lexicographic
+    sort: Option<SortOrder>,
+    #[clap(long)]
+    /// Name of the publication, required by the news format
+    publication_name: Option<String>,
+    #[clap(long)]
+    /// Language of the articles whose language cannot be detected, for the news format. Example: en
+    publication_language: Option<String>,
+    #[clap(long)]
+    /// Number of pages of the atom and rss formats, the most recently modified ones. Default value is 20
+    feed_size: Option<usize>,
+    #[clap(long)]
+    /// Title of the atom and rss formats. Default value is the URL of the first site to analyze
+    feed_title: Option<String>,
+    #[clap(long, parse(from_os_str))]
+    /// Template of the html format, an HTML file where {{tree}} is replaced by the list of the pages
+    html_template: Option<PathBuf>,
+    #[clap(short, long, parse(from_os_str))]
+    /// File to write the sitemap to, instead of stdout
+    output: Option<PathBuf>,
+    #[clap(long, parse(from_os_str))]
+    /// Update an existing XML sitemap: its pages are requested with If-Modified-Since and the ones which haven't changed
+    /// keep their entry without being analyzed again, following their previous links. The pages which now respond with
+    /// an error are removed. Only for the plain, txt and xml formats
+    update: Option<PathBuf>,
+    #[clap(long, parse(from_os_str))]
+    /// File keeping the ETags and the links of the pages between updates, see --update. Default value is the updated
+    /// sitemap followed by .cache.json
+    update_cache: Option<PathBuf>,
+    #[clap(long)]
+    /// Don't show the progress bar
+    no_progress: bool,
     #[clap(short, long)]
     verbose: bool,
 }
 
-pub(super) struct OtherOptions {
+impl CrawlArgs {
+    /// The values provided from the command line
+    fn to_config(&self) -> Config {
+        let flag = |value: bool| value.then_some(true);
+        Config {
+            sites: self.sites_to_analyze.clone(),
+            starting_points: self.starting_points.clone(),
+            skip_sites_as_starting_points: flag(self.skip_sites_to_analyze_as_starting_points),
+            additional_links: self.additional_links.clone(),
+            max_concurrent_tasks: self.max_concurrent_tasks,
+            remove_query_and_fragment: flag(self.remove_query_and_fragment),
+            max_depth: self.max_depth,
+            max_body_size: self.max_body_size,
+            timeout: self.timeout,
+            images: flag(self.images),
+            image_sites: self.image_sites.clone(),
+            videos: flag(self.videos),
+            hreflang: flag(self.hreflang),
+            priority: flag(self.priority),
+            changefreq_rules: self.changefreq_rules.clone(),
+            lastmod_history: self.lastmod_history.clone(),
+            graph: self.graph.clone(),
+            format: self.format,
+            sort: self.sort,
+            publication_name: self.publication_name.clone(),
+            publication_language: self.publication_language.clone(),
+            feed_size: self.feed_size,
+            feed_title: self.feed_title.clone(),
+            html_template: self.html_template.clone(),
+            output: self.output.clone(),
+            update: self.update.clone(),
+            update_cache: self.update_cache.clone(),
+            directory: self.directory.clone(),
+            pretty_urls: flag(self.pretty_urls),
+            state_file: self.state_file.clone(),
+            checkpoint_interval: self.checkpoint_interval,
+            grace_period: self.grace_period,
+            progress: self.no_progress.then_some(false),
+            verbose: flag(self.verbose),
+            site_overrides: Default::default(),
+        }
+    }
+}
+
+/// A crawl to run. Sites with overridden settings are crawled separately
+pub(super) struct Crawl {
+    pub(super) options: Options,
     pub(super) sites_to_analyze: HashSet<Url>,
+    /// Accepts the sites to analyze, except for the ones nested inside them which are crawled separately
+    pub(super) validator: Validator,
     pub(super) starting_points: HashSet<Url>,
-    pub(super) additional_links: Option<HashSet<Url>>,
+    pub(super) additional_links: HashSet<Url>,
 }
 
-#[inline]
-pub(super) fn from_cli() -> (Options, OtherOptions) {
-    Input::parse().into()
+pub(super) struct OtherOptions {
+    pub(super) output: OutputOptions,
+    /// The order of the entries of the sitemap
+    pub(super) sort: SortOrder,
+    /// Set for the news format
+    pub(super) publication: Option<Publication>,
+    /// Whether the language variants are added to the sitemap
+    pub(super) hreflang: bool,
+    pub(super) priority: bool,
+    pub(super) changefreq_rules: Vec<ChangefreqRule>,
+    pub(super) lastmod_history: Option<PathBuf>,
+    /// The entries of the sitemap being updated
+    pub(super) previous_entries: HashMap<Url, SitemapEntry>,
+    /// Where the ETags and the links of the pages are saved for the next update
+    pub(super) update_cache: Option<PathBuf>,
+    pub(super) graph: Option<(PathBuf, GraphFormat)>,
+    /// Set for the ndjson format, registered as an observer of the crawls
+    pub(super) ndjson_writer: Option<Arc<NdjsonWriter>>,
+    pub(super) additional_links: HashSet<Url>,
+    /// The timeout of the requests, `None` to wait forever
+    pub(super) timeout: Option<Duration>,
+    pub(super) directory: Option<PathBuf>,
+    pub(super) pretty_urls: bool,
+    /// Whether every file of the directory is crawled, instead of only the linked ones
+    pub(super) crawl_all_files: bool,
+    pub(super) cancellation_handle: CancellationHandle,
+    pub(super) stats: Arc<Stats>,
+    /// Shown only when stderr is a terminal
+    pub(super) progress_bar: Option<ProgressBar>,
 }
 
-impl From<Input> for (Options, OtherOptions) {
-    fn from(input: Input) -> Self {
-        if input.sites_to_analyze.is_empty() {
-            error("No domain has been provided.".to_string());
+/// How the sitemap is written
+#[derive(Clone)]
+pub(super) struct OutputOptions {
+    pub(super) format: OutputFormat,
+    /// Stdout if it's `None`
+    pub(super) path: Option<PathBuf>,
+    /// The template of the html format, empty for the other formats
+    pub(super) html_template: String,
+    /// Set for the atom and rss formats
+    pub(super) feed: Option<FeedSettings>,
+}
+
+#[derive(Clone)]
+pub(super) struct FeedSettings {
+    pub(super) title: String,
+    pub(super) link: Url,
+    /// Max number of pages
+    pub(super) size: usize,
+}
+
+/// The publication of the articles of a news sitemap
+pub(super) struct Publication {
+    pub(super) name: String,
+    /// Used for the articles without a language
+    pub(super) language: Option<String>,
+}
+
+/// What the program has to do
+pub(super) enum Mode {
+    Crawl(Vec<Crawl>, OtherOptions),
+    Validate {
+        files: Vec<PathBuf>,
+        base_url: Option<Url>,
+    },
+    Diff {
+        old: SitemapSource,
+        new: DiffTarget,
+        json: bool,
+        max_removed: Option<RemovalThreshold>,
+    },
+    Orphans {
+        reference: SitemapSource,
+        check: bool,
+        crawls: Vec<Crawl>,
+        other_options: OtherOptions,
+        /// Registered as an observer of the crawls
+        discovered: Arc<DiscoveredSites>,
+    },
+    Merge {
+        files: Vec<PathBuf>,
+        /// Write an index, with the base URL of the files
+        index: Option<Url>,
+        output: Option<PathBuf>,
+    },
+}
+
+pub(super) enum DiffTarget {
+    Sitemap(SitemapSource),
+    Crawl(Vec<Crawl>, Box<OtherOptions>),
+}
+
+/// Where a sitemap is loaded from
+#[derive(Debug, Clone)]
+pub(super) enum SitemapSource {
+    File(PathBuf),
+    Url(Url),
+}
+
+impl FromStr for SitemapSource {
+    type Err = String;
+
+    fn from_str(str: &str) -> Result<Self, Self::Err> {
+        Ok(match Url::parse(str) {
+            Ok(url) if filter_http(&url) => SitemapSource::Url(url),
+            _ => SitemapSource::File(PathBuf::from(str)),
+        })
+    }
+}
+
+/// The max number of URLs which can be removed
+#[derive(Debug, Clone, Copy)]
+pub(super) enum RemovalThreshold {
+    Count(usize),
+    /// Percentage of the previous URLs
+    Percentage(f64),
+}
+
+impl RemovalThreshold {
+    #[inline]
+    pub(super) fn is_exceeded(self, removed: usize, total: usize) -> bool {
+        match self {
+            RemovalThreshold::Count(count) => removed > count,
+            RemovalThreshold::Percentage(percentage) => removed as f64 > total as f64 * percentage / 100.0,
         }
-        if input.max_concurrent_tasks == 0 {
-            error("Concurrent tasks must be greater than zero.".to_string());
+    }
+}
+
+impl FromStr for RemovalThreshold {
+    type Err = String;
+
+    fn from_str(str: &str) -> Result<Self, Self::Err> {
+        match str.strip_suffix('%') {
+            Some(percentage) => percentage.parse().map(RemovalThreshold::Percentage).map_err(|_| format!(r#"invalid percentage "{}""#, str)),
+            None => str.parse().map(RemovalThreshold::Count).map_err(|_| format!(r#"invalid number "{}""#, str)),
         }
+    }
+}
+
+pub(super) fn from_cli() -> Mode {
+    let input = Input::parse();
+    match input.command {
+        None => {
+            let (crawls, other_options) = input.crawl.into();
+            Mode::Crawl(crawls, other_options)
+        },
+        Some(Command::Crawl(crawl)) => {
+            let (crawls, other_options) = crawl.into();
+            Mode::Crawl(crawls, other_options)
+        },
+        Some(Command::Validate { files, base_url }) => Mode::Validate {
+            files,
+            base_url: base_url.map(|base_url| url_validator(&base_url)),
+        },
+        Some(Command::Diff { old, new, json, max_removed, crawl }) => Mode::Diff {
+            old,
+            json,
+            max_removed,
+            new: match new {
+                Some(new) => DiffTarget::Sitemap(new),
+                None => {
+                    let (crawls, other_options) = crawl.into();
+                    DiffTarget::Crawl(crawls, Box::new(other_options))
+                },
+            },
+        },
+        Some(Command::Orphans { reference, check, crawl }) => {
+            let discovered = Arc::new(DiscoveredSites::new());
+            let observer: Arc<dyn CrawlObserver> = discovered.clone();
+            let (crawls, mut other_options) = crawl.into_crawls(&[observer]);
+            other_options.crawl_all_files = false;
+            Mode::Orphans { reference, check, crawls, other_options, discovered }
+        },
+        Some(Command::Merge { files, index, base_url, output }) => Mode::Merge {
+            files,
+            index: index.then(|| url_validator(&base_url.expect("--index requires --base-url"))),
+            output,
+        },
+    }
+}
 
-        let mut other_options = OtherOptions {
-            sites_to_analyze: input.sites_to_analyze.iter().map(|str| sites_to_analyze_validator(str)).collect(),
-            starting_points: input.starting_points.map_or(HashSet::new(), |vec| vec.iter().map(|str| url_validator(str)).collect()),
-            additional_links: input.additional_links.map(|vec| vec.iter().map(|str| url_parser(str)).collect()),
+impl From<CrawlArgs> for (Vec<Crawl>, OtherOptions) {
+    #[inline]
+    fn from(input: CrawlArgs) -> Self {
+        input.into_crawls(&[])
+    }
+}
+
+impl CrawlArgs {
+    /// Converts the arguments into the crawls to run, registering `observers` in each of them
+    fn into_crawls(self, observers: &[Arc<dyn CrawlObserver>]) -> (Vec<Crawl>, OtherOptions) {
+        let file_config = match &self.config {
+            Some(path) => Config::load(path).map(Some),
+            None => Config::discover(),
+        };
+        let file_config = match file_config {
+            Ok(file_config) => file_config.unwrap_or_default(),
+            Err(err) => error(format!("{:#}", err)),
         };
-        other_options.sites_to_analyze.iter().for_each(|url| { other_options.starting_points.insert(url.clone()); });
+        let config = self.to_config().or(file_config).with_defaults();
 
-        let options = Options::new(input.max_concurrent_tasks, input.remove_query_and_fragment, input.max_depth, input.verbose);
-        (options, other_options)
+        if self.dump_config {
+            match config.to_toml() {
+                Ok(toml) => print!("{}", toml),
+                Err(err) => error(format!("Cannot dump the config: {:#}", err)),
+            }
+            std::process::exit(0);
+        }
+
+        if config.sites.is_empty() {
+            error("No domain has been provided.".to_string());
+        }
+        if config.directory.is_some() && config.sites.len() != 1 {
+            error("Exactly one site to analyze must be provided when crawling a directory.".to_string());
+        }
+        if config.pretty_urls == Some(true) && config.directory.is_none() {
+            error("Pretty URLs can only be used when crawling a directory.".to_string());
+        }
+        if config.format == Some(OutputFormat::Txt) && config.output.is_none() {
+            error("An output file must be provided for the txt format.".to_string());
+        }
+        if config.format == Some(OutputFormat::News) && config.publication_name.is_none() {
+            error("A publication name must be provided for the news format.".to_string());
+        }
+        let graph = config.graph.clone().map(|path| match GraphFormat::from_path(&path) {
+            Some(format) => (path, format),
+            None => error(format!(r#"Cannot guess the format of the graph file "{}", use .dot, .gv, .graphml or .json"#, path.display())),
+        });
+        // The template is only used by the html format
+        let html_template = match &config.html_template {
+            _ if config.format != Some(OutputFormat::Html) => String::new(),
+            Some(path) => std::fs::read_to_string(path).unwrap_or_else(|err| error(format!(r#"Cannot read the template "{}": {}"#, path.display(), err))),
+            None => DEFAULT_HTML_TEMPLATE.to_string(),
+        };
+        if config.format == Some(OutputFormat::Html) && !html_template.contains(TREE_PLACEHOLDER) {
+            error(format!("The template must contain {}.", TREE_PLACEHOLDER));
+        }
+        if config.update.is_some() && !config.format.is_some_and(|format| matches!(format, OutputFormat::Plain | OutputFormat::Txt | OutputFormat::Xml)) {
+            error("Only the plain, txt and xml formats can be used to update a sitemap.".to_string());
+        }
+        let links_order = config.sort.is_some_and(|sort| sort != SortOrder::Lexicographic);
+        if config.update.is_some() && (config.priority == Some(true) || graph.is_some() || links_order) {
+            error("The priority, the graph and the depth and discovery orders cannot be used to update a sitemap, the links of the unchanged pages may not be known.".to_string());
+        }
+        if config.update_cache.is_some() && config.update.is_none() {
+            error("The cache file can only be used to update a sitemap.".to_string());
+        }
+        let update_cache = config.update_cache.clone().or_else(|| config.update.as_ref().map(|path| {
+            let mut cache = path.clone().into_os_string();
+            cache.push(".cache.json");
+            PathBuf::from(cache)
+        }));
+        let previous_entries: HashMap<Url, SitemapEntry> = match config.update.as_deref().map(load_sitemap) {
+            Some(Ok(sitemap)) if sitemap.kind == SitemapKind::UrlSet => sitemap.entries.into_iter().map(|entry| (entry.loc.clone(), entry)).collect(),
+            Some(Ok(_)) => error("Cannot update a sitemap index.".to_string()),
+            Some(Err(err)) => error(format!("{:#}", err)),
+            None => HashMap::new(),
+        };
+        let mut cache = match update_cache.as_deref().map(load_update_cache) {
+            Some(Ok(cache)) => cache,
+            Some(Err(err)) => error(format!("{:#}", err)),
+            None => HashMap::new(),
+        };
+        // The pages missing from the cache are analyzed again, since their links are needed to find the other pages
+        let known_pages: HashMap<Url, RequestConditions> = previous_entries.values()
+        .filter_map(|entry| {
+            let cached = cache.remove(&entry.loc)?;
+            Some((entry.loc.clone(), RequestConditions {
+                last_modified: entry.lastmod.as_deref().and_then(parse_w3c_datetime).map(Into::into),
+                etag: cached.etag,
+                links: cached.links,
+            }))
+        })
+        .filter(|(_, conditions)| conditions.last_modified.is_some() || conditions.etag.is_some())
+        .collect();
+        if self.resume && config.state_file.is_none() {
+            error("A state file must be provided to resume the crawl.".to_string());
+        }
+
+        let sites_to_analyze: Vec<Url> = config.sites.iter().map(|str| sites_to_analyze_validator(str)).collect();
+        let mut site_configs: Vec<(Url, Config)> = Vec::new();
+        for (site, site_config) in &config.site_overrides {
+            let site = sites_to_analyze_validator(site);
+            if !sites_to_analyze.contains(&site) {
+                error(format!(r#"Overrides are provided for "{site}", which is not a site to analyze"#));
+            }
+            site_configs.push((site, site_config.apply(&config)));
+        }
+
+        let ndjson_writer = (config.format == Some(OutputFormat::Ndjson)).then(|| Arc::new(NdjsonWriter::new(config.output.clone())));
+        let observers: Vec<Arc<dyn CrawlObserver>> = observers.iter().cloned()
+        .chain(ndjson_writer.clone().map(|ndjson_writer| ndjson_writer as Arc<dyn CrawlObserver>))
+        .collect();
+
+        let stats = Arc::new(Stats::new());
+        let progress_bar = (config.progress == Some(true) && std::io::stderr().is_terminal()).then(progress_bar);
+        let cancellation_handle = CancellationHandle::new();
+        let printer = Arc::new(Printer { verbose: config.verbose == Some(true), progress_bar: progress_bar.clone() });
+
+        // Global additional links are added only once, see OtherOptions
+        let default_config = Config { additional_links: None, ..config.clone() };
+        let mut groups: Vec<(HashSet<Url>, &Config)> = site_configs.iter().map(|(site, site_config)| (HashSet::from([site.clone()]), site_config)).collect();
+        let default_sites: HashSet<Url> = sites_to_analyze.into_iter().filter(|site| !site_configs.iter().any(|(other, _)| site == other)).collect();
+        if !default_sites.is_empty() {
+            groups.insert(0, (default_sites, &default_config));
+        }
+        if groups.len() > 1 && config.state_file.is_some() {
+            error("A state file cannot be used together with per-site overrides.".to_string());
+        }
+
+        let crawls = groups.into_iter().map(|(sites_to_analyze, config)| {
+            let max_concurrent_tasks = config.max_concurrent_tasks.expect("default value is set");
+            if max_concurrent_tasks == 0 {
+                error("Concurrent tasks must be greater than zero.".to_string());
+            }
+
+            let mut starting_points: HashSet<Url> = config.starting_points.as_ref().map_or(HashSet::new(), |vec| vec.iter().map(|str| url_validator(str)).collect());
+            if config.skip_sites_as_starting_points != Some(true) {
+                starting_points.extend(sites_to_analyze.iter().cloned());
+            }
+            // The overridden sites nested inside the ones of this crawl are left to their own crawl
+            let validator = Validator::new(sites_to_analyze.iter().cloned())
+            .excluding(site_configs.iter().map(|(site, _)| site).filter(|site| !sites_to_analyze.contains(*site)).cloned());
+
+            let options = Options::builder()
+            .set_max_task_count(max_concurrent_tasks)
+            .set_remove_query_and_fragment(config.remove_query_and_fragment == Some(true))
+            .set_max_recursion(config.max_depth.expect("default value is set"))
+            .add_observer(printer.clone())
+            .add_observer(stats.clone())
+            .set_state_file(config.state_file.clone())
+            .set_checkpoint_interval(Duration::from_secs(config.checkpoint_interval.expect("default value is set")))
+            .set_cancellation_handle(Some(cancellation_handle.clone()))
+            .set_shutdown_grace_period(Duration::from_secs(config.grace_period.expect("default value is set")))
+            .set_max_body_size(config.max_body_size)
+            .set_collect_images(config.images == Some(true))
+            .set_image_validator(Validator::new(config.image_sites.iter().flatten().map(|str| url_validator(str))))
+            .set_collect_videos(config.videos == Some(true))
+            .set_collect_articles(config.format == Some(OutputFormat::News))
+            .set_collect_alternates(config.hreflang == Some(true))
+            // The links are saved in the cache of the updates
+            .set_collect_links(config.priority == Some(true) || graph.is_some() || links_order || config.update.is_some())
+            .set_remove_error_pages(config.update.is_some())
+            .set_collect_titles(config.format.is_some_and(|format| format == OutputFormat::Html || format.is_record() || format.is_feed()))
+            .set_collect_descriptions(config.format.is_some_and(OutputFormat::is_feed))
+            .set_collect_canonicals(config.format.is_some_and(OutputFormat::is_record))
+            .set_known_pages(known_pages.clone());
+            let mut options = observers.iter().fold(options, |options, observer| options.add_observer(observer.clone())).build();
+
+            if self.resume {
+                let state_file = config.state_file.as_ref().expect("the state file is checked above");
+                match CrawlState::load(state_file) {
+                    Ok(state) => options.set_resume_state(Some(state)),
+                    Err(err) => error(format!("{:#}", err)),
+                }
+            }
+
+            Crawl {
+                options,
+                sites_to_analyze,
+                validator,
+                starting_points,
+                additional_links: config.additional_links.as_ref().map_or(HashSet::new(), |vec| vec.iter().map(|str| url_parser(str)).collect()),
+            }
+        }).collect();
+
+        let format = config.format.expect("default value is set");
+        let output = OutputOptions {
+            format,
+            path: config.output.clone(),
+            html_template,
+            feed: format.is_feed().then(|| {
+                let link = sites_to_analyze_validator(&config.sites[0]);
+                FeedSettings {
+                    title: config.feed_title.clone().unwrap_or_else(|| link.to_string()),
+                    link,
+                    size: config.feed_size.expect("default value is set"),
+                }
+            }),
+        };
+        let other_options = OtherOptions {
+            output,
+            sort: config.sort.expect("default value is set"),
+            publication: (config.format == Some(OutputFormat::News)).then(|| Publication {
+                name: config.publication_name.clone().expect("the publication name is checked above"),
+                language: config.publication_language.clone(),
+            }),
+            hreflang: config.hreflang == Some(true),
+            priority: config.priority == Some(true),
+            changefreq_rules: config.changefreq_rules.iter().flatten().map(|rule| rule.parse().unwrap_or_else(|err| error(err))).collect(),
+            lastmod_history: config.lastmod_history.clone(),
+            previous_entries,
+            update_cache,
+            graph,
+            ndjson_writer,
+            additional_links: config.additional_links.as_ref().map_or(HashSet::new(), |vec| vec.iter().map(|str| url_parser(str)).collect()),
+            timeout: Some(config.timeout.expect("default value is set")).filter(|&timeout| timeout != 0).map(Duration::from_secs),
+            directory: config.directory.clone(),
+            pretty_urls: config.pretty_urls == Some(true),
+            crawl_all_files: true,
+            cancellation_handle,
+            stats,
+            progress_bar,
+        };
+        (crawls, other_options)
     }
 }
 
@@ -108,8 +710,48 @@ fn sites_to_analyze_validator(url: &str) -> Url {
     site_to_analyze
 }
 
-#[test]
-fn verify_app() {
-    use clap::CommandFactory;
-    Input::command().debug_assert();
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verify_app() {
+        use clap::CommandFactory;
+        Input::command().debug_assert();
+    }
+
+    #[tokio::test]
+    async fn nested_site_overrides() {
+        use sitemap_generator::{analyze_with_fetcher, MemoryFetcher};
+
+        let config_file = std::env::temp_dir().join(format!("sitemap_generator_config_{}.toml", std::process::id()));
+        std::fs::write(&config_file, r#"
+        sites = ["https://example.com/", "https://example.com/blog/"]
+
+        [site."https://example.com/blog/"]
+        max-depth = 1
+    "#).unwrap();
+        let input = Input::try_parse_from(["sitemap_generator", "--no-progress", "--config", config_file.to_str().unwrap()]).unwrap();
+        let (crawls, _) = input.crawl.into_crawls(&[]);
+        std::fs::remove_file(&config_file).unwrap();
+        assert_eq!(crawls.len(), 2);
+
+        let url = |str: &str| Url::parse("https://example.com/").unwrap().join(str).unwrap();
+        let fetcher = MemoryFetcher::new()
+        .with_page(url("/"), r#"<a href="/about">About</a> <a href="/blog/post">Post</a>"#)
+        .with_page(url("/about"), "About")
+        .with_page(url("/blog/"), r#"<a href="/blog/post">Post</a>"#)
+        .with_page(url("/blog/post"), r#"<a href="/blog/old">Old</a>"#)
+        .with_page(url("/blog/old"), "Old");
+
+        let mut found: Vec<Vec<String>> = Vec::new();
+        for crawl in crawls {
+            let result = analyze_with_fetcher(fetcher.clone(), crawl.starting_points.into_iter(), crawl.validator, crawl.options).await;
+            let mut sites: Vec<String> = result.sites.iter().map(|site| site.path().to_string()).collect();
+            sites.sort();
+            found.push(sites);
+        }
+        // The blog is only crawled with its own max depth, so its old post isn't reached
+        assert_eq!(found, [vec!["/", "/about"], vec!["/blog/", "/blog/post"]]);
+    }
 }
@@ -1,82 +1,133 @@
 #![forbid(unsafe_code)]
 
-use std::cell::Cell;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
-use anyhow::anyhow;
+use chrono::{DateTime, FixedOffset, Utc};
 use futures::{stream, StreamExt};
-use reqwest::{Client, StatusCode};
-use reqwest::redirect::{Attempt, Policy};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
 use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
 use tokio::sync::Semaphore;
+use tokio::time::{Instant, sleep};
 use url::Url;
 
+pub use crate::cancellation::CancellationHandle;
+pub use crate::checkpoint::{CrawlState, FrontierEntry};
+use crate::checkpoint::Frontier;
+pub use crate::error::CrawlError;
+pub use crate::directory::{analyze_directory, DirectoryAnalysis, DirectoryFetcher, MissingFile};
+pub use crate::feed::{Feed, FeedEntry};
+pub use crate::graph::{Edge, GraphFormat, Link, LinkGraph};
+pub use crate::heuristics::{Changefreq, ChangefreqRule, LastmodHistory, page_rank, priorities};
+pub use crate::hreflang::{hreflang_groups, HreflangGroups, HreflangIssue};
+pub use crate::html_sitemap::HtmlSitemap;
+pub use crate::fetcher::{Fetcher, FetchResponse, MemoryFetcher, ReqwestFetcher, RequestConditions};
+pub use crate::observer::{CrawlObserver, Observers, RejectionReason};
+pub use crate::order::{Discovery, discovery_order, SortOrder};
+pub use crate::orphans::{check_existence, DiscoveredSites, OrphanReport};
+pub use crate::sitemap::{Alternate, fetch_sitemap, LastmodChange, News, Sitemap, SitemapDiff, SitemapEntry, SitemapKind, Video};
+pub use crate::validation::{parse_w3c_datetime, SitemapIssue, SitemapProblem, validate_sitemap};
 pub use crate::options::*;
-use crate::site_analyzer::types::TaskInfo;
+use crate::site_analyzer::types::{CrawlContext, Message, TaskInfo};
+pub use crate::events::{analyze_stream, analyze_stream_with_fetcher, CrawlEvent};
 pub use crate::site_analyzer::types::Validator;
-use crate::utils::println;
 
 pub mod utils;
+pub mod fetcher;
+pub mod directory;
+pub mod checkpoint;
+pub mod cancellation;
+pub mod error;
+pub mod events;
+pub mod feed;
+pub mod graph;
+pub mod heuristics;
+pub mod hreflang;
+pub mod html_sitemap;
+pub mod observer;
+pub mod order;
+pub mod orphans;
+pub mod sitemap;
+pub mod validation;
 pub(crate) mod options;
+#[cfg(test)]
+mod test_utils;
 
 pub(crate) mod site_analyzer {
+    pub mod metadata;
     pub mod processing;
     pub mod types;
 }
 
-const APP_USER_AGENT: &str = concat!(
-env!("CARGO_PKG_NAME"),
-"/",
-env!("CARGO_PKG_VERSION"),
-);
+pub async fn analyze(sites_to_analyze: impl Iterator<Item=Url>, validator: Validator, options: Options) -> CrawlResult {
+    analyze_with_fetcher(ReqwestFetcher::new(), sites_to_analyze, validator, options).await
+}
+
+pub async fn analyze_with_fetcher<F: Fetcher>(fetcher: F, sites_to_analyze: impl Iterator<Item=Url>, validator: Validator, options: Options) -> CrawlResult {
+    crawl(fetcher, sites_to_analyze, validator, options, None).await
+}
 
-pub async fn analyze(sites_to_analyze: impl Iterator<Item=Url>, validator: Validator, mut options: Options) -> impl IntoIterator<Item=Arc<Url>> {
+pub(crate) async fn crawl<F: Fetcher>(fetcher: F, sites_to_analyze: impl Iterator<Item=Url>, validator: Validator, mut options: Options, events: Option<mpsc::Sender<CrawlEvent>>) -> CrawlResult {
     let max_task_count = options.max_task_count();
     let max_recursion = options.max_recursion();
-    let verbose = options.verbose();
-    let (tx, mut rx): (UnboundedSender<TaskInfo>, UnboundedReceiver<TaskInfo>) = mpsc::unbounded_channel();
-
-    if verbose {
-        let (verbose_tx, mut verbose_rx) = mpsc::unbounded_channel();
-        options.set_verbose_sender(Some(verbose_tx));
-
-        tokio::spawn(async move {
-            let mut str = String::from("Analyzing: \"__\"\n"); // "__" will be replaced with the site URL
-            let start = 12;
-            while let Some(site) = verbose_rx.recv().await {
-                str.replace_range(start..(str.len() - 2), site.as_str());
-                println(&str).await;
-            }
-        });
-    }
+    let collect_links = options.collect_links();
+    let resume_state = options.take_resume_state();
+    let observers = options.observers().clone();
+    let (tx, mut rx): (UnboundedSender<Message>, UnboundedReceiver<Message>) = mpsc::unbounded_channel();
 
-    // The first Arc and the Mutex are necessary to make sites movable between threads
-    // The Cell is used at the end of this function to allow the HashSet to be returned
+    // The Arc and the Mutex are necessary to make sites movable between threads
     let sites = Sites::new();
-    let options = Arc::new(options);
-    let sem = Arc::new(Semaphore::new(max_task_count));
+    let context = Arc::new(CrawlContext {
+        fetcher,
+        sites: sites.clone(),
+        semaphore: Semaphore::new(max_task_count),
+        options,
+        frontier: Frontier::default(),
+        abort: CancellationHandle::new(),
+        events,
+        errors: Mutex::new(Vec::new()),
+        pages: Mutex::new(HashMap::new()),
+    });
 
-    let client = create_client(sites.clone(), validator.clone(), options.clone());
+    let frontier = match resume_state {
+        Some(state) => {
+            sites.access_map(|hashset| hashset.extend(state.visited.into_iter().map(Arc::new)));
+            context.pages.lock().unwrap().extend(state.pages.into_iter().map(|(url, page)| (Arc::new(url), page)));
+            state.frontier
+        },
+        None => Vec::new(),
+    };
 
     {
-        stream::iter(sites_to_analyze)
-        .map(Arc::new)
-        .filter(|site| std::future::ready(validator.is_valid(site)))
-        .filter(|site| {
+        stream::iter(frontier.into_iter().map(|entry| (entry.url, entry.recursion))
+        .chain(sites_to_analyze.map(|site| (site, max_recursion))))
+        .map(|(site, recursion)| (Arc::new(site), recursion))
+        .filter(|(site, _)| {
+            let valid = validator.is_valid(site);
+            if !valid {
+                observers.on_page_rejected(site, RejectionReason::OutOfScope);
+            }
+            std::future::ready(valid)
+        })
+        .filter(|(site, _)| {
             let site = site.clone();
-            async {
-                sites.access_map(|hashset| hashset.insert(site))
+            let inserted = sites.access_map(|hashset| hashset.insert(site.clone()));
+            if inserted {
+                observers.on_page_accepted(&site);
             }
+            std::future::ready(inserted)
         })
-        .for_each(|site| {
+        .for_each(|(site, recursion)| {
             TaskInfo {
                 site,
                 tx: tx.clone(),
                 validator: validator.clone(),
-                recursion: max_recursion,
-            }.spawn_task(client.clone(), sem.clone(), options.clone())
+                recursion,
+                referrer: None,
+            }.spawn_task(context.clone())
         })
         .await;
     }
@@ -84,29 +135,190 @@ pub async fn analyze(sites_to_analyze: impl Iterator<Item=Url>, validator: Valid
     // Drop our sender
     drop(tx);
 
-    while let Some(task_info) = rx.recv().await {
-        if /*validator.is_valid(&task_info.site) check already done &&*/ sites.access_map(|hashset| hashset.insert(task_info.site.clone())) {
-            task_info.spawn_task(client.clone(), sem.clone(), options.clone()).await;
+    let state_file = context.options.state_file().cloned();
+    let checkpoint_period = context.options.checkpoint_interval();
+    let mut checkpoint_interval = tokio::time::interval_at(Instant::now() + checkpoint_period, checkpoint_period);
+
+    let cancellation_handle = context.options.cancellation_handle().cloned().unwrap_or_default();
+    let grace_period = sleep(Duration::ZERO);
+    tokio::pin!(grace_period);
+    let mut cancelled = false;
+
+    loop {
+        let message = tokio::select! {
+            message = rx.recv() => message,
+            _ = checkpoint_interval.tick(), if state_file.is_some() => {
+                save_state(&context, state_file.as_ref().unwrap()).await;
+                continue;
+            },
+            _ = cancellation_handle.cancelled(), if !cancelled => {
+                cancelled = true;
+                grace_period.as_mut().reset(Instant::now() + context.options.shutdown_grace_period());
+                continue;
+            },
+            _ = &mut grace_period, if cancelled && !context.abort.is_cancelled() => {
+                // Stop the tasks which are still running
+                context.abort.cancel();
+                continue;
+            },
+        };
+
+        match message {
+            Some(Message::Task(task_info)) => {
+                if /*validator.is_valid(&task_info.site) check already done &&*/ sites.access_map(|hashset| hashset.insert(task_info.site.clone())) {
+                    observers.on_page_accepted(&task_info.site);
+                    if cancelled || context.abort.is_cancelled() {
+                        task_info.postpone(&context);
+                    } else {
+                        task_info.spawn_task(context.clone()).await;
+                    }
+                }
+            },
+            Some(Message::Done(site)) => {
+                context.frontier.finish(&site);
+                observers.on_page_done(&site);
+            },
+            None => break,
+        }
+    }
+
+    if let Some(state_file) = &state_file {
+        save_state(&context, state_file).await;
+    }
+
+    // After a cancellation the sites which haven't been analyzed are only kept in the state file
+    let pending = context.frontier.pending();
+    sites.access_map(|hashset| {
+        for site in &pending {
+            hashset.remove(site);
+        }
+    });
+
+    let errors = std::mem::take(&mut *context.errors.lock().unwrap());
+    let mut pages = std::mem::take(&mut *context.pages.lock().unwrap());
+    drop(context);
+
+    let sites = sites.access_map(std::mem::take);
+    // Sites can be removed after being fetched, e.g. when another page permanently redirects to them.
+    // The removed error pages and redirects are kept, since they tell why they aren't in the sitemap.
+    pages.retain(|url, page| sites.contains(url) || page.is_error_or_redirect());
+    // With concurrent requests a page keeps the depth of the first path found to it, use the shortest one instead.
+    // The links are always kept during the crawl for this
+    let starting_points = pages.iter().filter(|(_, page)| page.depth == 0).map(|(url, _)| (**url).clone());
+    for (url, discovery) in discovery_order(&pages, starting_points) {
+        if let Some(page) = pages.get_mut(&url) {
+            page.depth = page.depth.min(discovery.depth);
         }
     }
+    if !collect_links {
+        pages.values_mut().for_each(|page| page.links = Vec::new());
+    }
+    observers.on_crawl_finished(sites.len());
+    CrawlResult {
+        sites,
+        errors,
+        pages,
+    }
+}
 
-    drop(client);
+async fn save_state<F: Fetcher>(context: &CrawlContext<F>, state_file: &Path) {
+    let state = context.frontier.snapshot(&context.sites, &context.pages);
+    if let Err(err) = state.save(state_file).await {
+        context.options.observers().on_checkpoint_failed(state_file, &err);
+    }
+}
 
-    let make_it_compile = sites.inner.lock().unwrap().replace(HashSet::with_capacity(0));
-    make_it_compile
+/// The outcome of a crawl. Iterating over it yields the sites found.
+#[derive(Debug, Default)]
+pub struct CrawlResult {
+    pub sites: HashSet<Arc<Url>>,
+    /// The sites which couldn't be analyzed, with the reason
+    pub errors: Vec<(Arc<Url>, CrawlError)>,
+    /// Information about the sites which have been fetched
+    pub pages: HashMap<Arc<Url>, PageInfo>,
+}
+
+/// Information about a fetched page
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct PageInfo {
+    /// `304 Not Modified` for the known pages which haven't changed, see [`OptionsBuilder::set_known_pages`]
+    #[serde(with = "crate::checkpoint::status_code")]
+    pub status: StatusCode,
+    /// From the `Content-Type` header
+    pub content_type: Option<String>,
+    /// From the `Last-Modified` header. For pages which haven't changed it's the one of [`OptionsBuilder::set_known_pages`]
+    /// if the response doesn't include it
+    pub last_modified: Option<DateTime<Utc>>,
+    /// From the `ETag` header, like `last_modified`
+    pub etag: Option<String>,
+    /// The time taken by the request of the page, including the download of the body of HTML pages
+    pub response_time: Duration,
+    /// The size of the body. When the body isn't downloaded, see [`OptionsBuilder::set_analyze_html_only`], it's taken from the `Content-Length` header
+    pub size: Option<usize>,
+    /// The content of the `<title>` element, see [`OptionsBuilder::set_collect_titles`]
+    pub title: Option<String>,
+    /// The content of `<meta name="description">`, see [`OptionsBuilder::set_collect_descriptions`]
+    pub description: Option<String>,
+    /// From `<link rel="canonical">`, see [`OptionsBuilder::set_collect_canonicals`]
+    pub canonical: Option<Url>,
+    /// The images of the page, in order of appearance, see [`OptionsBuilder::set_collect_images`]
+    pub images: Vec<Url>,
+    /// The videos of the page, see [`OptionsBuilder::set_collect_videos`]
+    pub videos: Vec<Video>,
+    /// Present if the page is an article, see [`OptionsBuilder::set_collect_articles`]
+    pub article: Option<Article>,
+    /// The language variants declared by the page, see [`OptionsBuilder::set_collect_alternates`]
+    pub alternates: Vec<Alternate>,
+    /// The number of links followed from a starting point to reach the page
+    pub depth: usize,
+    /// The links of the page inside the sites to analyze, see [`OptionsBuilder::set_collect_links`]
+    pub links: Vec<Link>,
+    /// The page in which the link to this one was first found, `None` for the starting points
+    pub referrer: Option<Url>,
+    /// The target of the redirect, for pages responding with a redirect status
+    pub redirect: Option<Url>,
+}
+
+impl PageInfo {
+    /// Whether the page is kept in [`CrawlResult::pages`] even if it isn't in the sitemap,
+    /// because it responded with an error status or with a redirect
+    #[inline]
+    pub fn is_error_or_redirect(&self) -> bool {
+        self.status.is_client_error() || self.status.is_server_error() || self.redirect.is_some()
+    }
+}
+
+/// A published article, such as a blog post
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Article {
+    pub publication_date: DateTime<FixedOffset>,
+    pub title: Option<String>,
+    /// An ISO 639 code, like `en`
+    pub language: Option<String>,
+}
+
+impl IntoIterator for CrawlResult {
+    type Item = Arc<Url>;
+    type IntoIter = std::collections::hash_set::IntoIter<Arc<Url>>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.sites.into_iter()
+    }
 }
 
 #[derive(Clone)]
 #[repr(transparent)]
 pub struct Sites {
-    inner: Arc<Mutex<Cell<HashSet<Arc<Url>>>>>,
+    inner: Arc<Mutex<HashSet<Arc<Url>>>>,
 }
 
 impl Sites {
     #[allow(clippy::new_without_default)]
     pub fn new() -> Sites {
         Sites {
-            inner: Arc::new(Mutex::new(Cell::new(std::collections::HashSet::new()))),
+            inner: Arc::new(Mutex::new(HashSet::new())),
         }
     }
 
@@ -115,64 +327,6 @@ impl Sites {
         F: FnOnce(&mut HashSet<Arc<Url>>) -> R,
         R: Sized
     {
-        f(self.inner.lock().unwrap().get_mut())
+        f(&mut self.inner.lock().unwrap())
     }
 }
-
-fn create_client(sites: Sites, validator: Validator, options: Arc<Options>) -> Client {
-    reqwest::Client::builder()
-    .user_agent(APP_USER_AGENT)
-    .pool_idle_timeout(Some(Duration::from_secs(2))) // See https://github.com/hyperium/hyper/issues/2136#issuecomment-589488526
-    .redirect(Policy::custom(move |attempt| {
-        fn check_validated(validator: &Validator, attempt: &Attempt) -> Option<anyhow::Error> {
-            if !validator.is_valid(attempt.url()) {
-                let previous = match attempt.previous().last() {
-                    Some(prev) => {
-                        prev.as_str()
-                    },
-                    None => "None",
-                };
-                Some(anyhow!(r#""{}" is redirecting to "{}", which is not on to analyze"#, previous, attempt.url().as_str()))
-            } else {
-                None
-            }
-        }
-
-        if attempt.status() == StatusCode::MOVED_PERMANENTLY {
-            sites.access_map(|hashset| hashset.remove(attempt.url()));
-
-            if options.verbose() {
-                tokio::spawn(println(format!("Removed \"{}\"\n", attempt.url().as_str())));
-            }
-
-            if let Some(error) = check_validated(&validator, &attempt) {
-                return attempt.error(error);
-            }
-            Policy::limited(10).redirect(attempt)
-        } else {
-            if let Some(error) = check_validated(&validator, &attempt) {
-                return attempt.error(error);
-            }
-
-            let arc = Arc::new(attempt.url().clone());
-            let insert_result = if options.verbose() {
-                if sites.access_map(|hashset| hashset.insert(arc.clone())) {
-                    if let Some(tx) = options.verbose_sender() {
-                        let _ = tx.send(arc);
-                    }
-                    true
-                } else {
-                    false
-                }
-            } else {
-                sites.access_map(|hashset| hashset.insert(arc))
-            };
-            if insert_result {
-                Policy::limited(10).redirect(attempt)
-            } else {
-                attempt.stop()
-            }
-        }
-    }))
-    .build().unwrap()
-}
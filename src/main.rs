@@ -1,33 +1,422 @@
 #![allow(non_snake_case)]
 #![forbid(unsafe_code)]
 
-pub use sitemap_generator::{Options, utils, Validator};
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
+use anyhow::Context;
+use reqwest::StatusCode;
+use chrono::{DateTime, SecondsFormat, Utc};
+use url::Url;
+
+pub use sitemap_generator::{Article, CancellationHandle, DirectoryFetcher, News, Options, PageInfo, Sitemap, SitemapEntry, SitemapKind, utils, Validator};
+use sitemap_generator::{ChangefreqRule, Feed, hreflang_groups, HtmlSitemap, LastmodHistory, LinkGraph, MissingFile, ReqwestFetcher};
+use sitemap_generator::sitemap::MAX_NEWS_AGE_HOURS;
+
+use crate::config::OutputFormat;
+use crate::input::{Crawl, Mode, OtherOptions, OutputOptions, Publication};
+use crate::records::Record;
+
+mod commands;
+mod config;
 mod input;
+mod printer;
+mod progress;
+mod records;
+
+/// Exit code used when the crawl has been interrupted and the printed sitemap is partial
+const PARTIAL_EXIT_CODE: i32 = 3;
+/// Exit code used when the diff exceeds the allowed number of removed URLs
+const REMOVALS_EXIT_CODE: i32 = 4;
+/// Exit code used when the program is forcefully stopped
+const INTERRUPTED_EXIT_CODE: i32 = 130;
 
 fn main() {
-    let (options, other_options) = input::from_cli();
+    match input::from_cli() {
+        Mode::Crawl(crawls, other_options) => {
+            let ndjson_writer = other_options.ndjson_writer.clone();
+            let output = other_options.output.clone();
+            let result = run_crawls(crawls, other_options);
+            let written = match ndjson_writer {
+                // The records have been written during the crawl
+                Some(ndjson_writer) => ndjson_writer.finish(),
+                None => write_sitemap(&result, &output),
+            };
+            if let Err(err) = written {
+                eprintln!("Cannot write the sitemap: {:#}", err);
+                std::process::exit(1);
+            }
+            if result.partial {
+                std::process::exit(PARTIAL_EXIT_CODE);
+            }
+        },
+        Mode::Validate { files, base_url } => std::process::exit(commands::validate(&files, base_url.as_ref())),
+        Mode::Diff { old, new, json, max_removed } => std::process::exit(commands::diff(&old, new, json, max_removed)),
+        Mode::Orphans { reference, check, crawls, other_options, discovered } => {
+            std::process::exit(commands::orphans(&reference, check, crawls, other_options, &discovered))
+        },
+        Mode::Merge { files, index, output } => std::process::exit(commands::merge(&files, index, output.as_deref())),
+    }
+}
 
-    let sites_to_analyze = other_options.starting_points;
+/// The sites found by the crawls
+pub(crate) struct CrawlOutput {
+    pub(crate) sitemap: Sitemap,
+    /// The pages analyzed by the crawls
+    pub(crate) pages: HashMap<Arc<Url>, PageInfo>,
+    /// Whether the crawl has been interrupted
+    pub(crate) partial: bool,
+}
 
+/// Runs the crawls one after the other, printing the statistics at the end
+pub(crate) fn run_crawls(crawls: Vec<Crawl>, other_options: OtherOptions) -> CrawlOutput {
     // Start tokio
-    let sites = tokio::runtime::Builder::new_multi_thread()
+    let runtime = tokio::runtime::Builder::new_multi_thread()
     .enable_all()
     .thread_name("SitemapGenerator")
     .build()
-    .expect("Failed building the Runtime")
-    .block_on(sitemap_generator::analyze(sites_to_analyze.into_iter(), Validator::new(other_options.sites_to_analyze.into_iter()), options));
+    .expect("Failed building the Runtime");
 
-    let mut i = 0usize;
-    for site in sites {
-        i += 1; // sites is an iterator, cannot use len()
-        println!("{}", &*site);
+    let cancellation_handle = other_options.cancellation_handle.clone();
+    runtime.spawn(cancel_on_signal(cancellation_handle.clone()));
+    if let Some(progress_bar) = &other_options.progress_bar {
+        runtime.spawn(progress::update_progress_bar(progress_bar.clone(), other_options.stats.clone()));
     }
 
-    if let Some(additional_links) = other_options.additional_links {
-        i += additional_links.len();
-        additional_links.iter().map(|site| site.to_string()).for_each(|site| println!("{}", site));
+    let mut sites: HashSet<Arc<Url>> = HashSet::new();
+    let mut pages: HashMap<Arc<Url>, PageInfo> = HashMap::new();
+    let mut missing: Vec<MissingFile> = Vec::new();
+    let mut additional_links = other_options.additional_links;
+    let mut sites_to_analyze: Vec<Url> = Vec::new();
+    let mut starting_points: Vec<Url> = Vec::new();
+    let fetcher = match other_options.timeout {
+        Some(timeout) => ReqwestFetcher::with_timeout(timeout),
+        None => ReqwestFetcher::new(),
+    };
+    for crawl in crawls {
+        if cancellation_handle.is_cancelled() {
+            break;
+        }
+        additional_links.extend(crawl.additional_links);
+        sites_to_analyze.extend(crawl.sites_to_analyze.iter().cloned());
+        starting_points.extend(crawl.starting_points.iter().cloned());
+
+        match &other_options.directory {
+            Some(directory) => {
+                let base_url = crawl.sites_to_analyze.into_iter().next().expect("exactly one site to analyze");
+                let fetcher = DirectoryFetcher::new(directory, base_url)
+                .set_pretty_urls(other_options.pretty_urls)
+                .set_crawl_all_files(other_options.crawl_all_files);
+                let analysis = match runtime.block_on(sitemap_generator::analyze_directory(fetcher, crawl.starting_points.into_iter(), crawl.options)) {
+                    Ok(analysis) => analysis,
+                    Err(err) => {
+                        eprintln!("Cannot crawl the directory: {}", err);
+                        std::process::exit(1);
+                    },
+                };
+                sites.extend(analysis.sites);
+                pages.extend(analysis.pages);
+                missing.extend(analysis.missing);
+            },
+            None => {
+                let result = runtime.block_on(sitemap_generator::analyze_with_fetcher(fetcher.clone(), crawl.starting_points.into_iter(), crawl.validator, crawl.options));
+                sites.extend(result.sites);
+                pages.extend(result.pages);
+            },
+        }
+    }
+    if let Some(progress_bar) = &other_options.progress_bar {
+        progress_bar.finish_and_clear();
+    }
+    for missing in &missing {
+        match &missing.referrer {
+            Some(referrer) => eprintln!("Missing file for \"{}\", linked from \"{}\"", missing.url, referrer),
+            None => eprintln!("Missing file for \"{}\"", missing.url),
+        }
     }
 
-    println!("Done! ({})", i);
+    if let Some((path, format)) = &other_options.graph {
+        let graph = LinkGraph::from_pages(&pages);
+        let result = commands::create_output(Some(path)).and_then(|mut writer| {
+            graph.write(*format, &mut writer)?;
+            writer.flush()?;
+            Ok(())
+        });
+        if let Err(err) = result {
+            eprintln!(r#"Cannot write the graph to "{}": {:#}"#, path.display(), err);
+        }
+    }
+
+    let mut alternates = if other_options.hreflang {
+        let groups = hreflang_groups(&pages, &Validator::new(sites_to_analyze.into_iter()));
+        for issue in &groups.issues {
+            eprintln!("hreflang: {}", issue);
+        }
+        groups.alternates
+    } else {
+        HashMap::new()
+    };
+
+    let priorities = if other_options.priority {
+        sitemap_generator::priorities(&pages)
+    } else {
+        HashMap::new()
+    };
+    let history = other_options.lastmod_history.as_deref().and_then(|path| update_history(path, &pages, cancellation_handle.is_cancelled()));
+    // The pages which haven't been analyzed would be missing from the cache of a cancelled crawl
+    if let Some(path) = other_options.update_cache.as_deref().filter(|_| !cancellation_handle.is_cancelled()) {
+        if let Err(err) = commands::save_update_cache(path, &pages) {
+            eprintln!(r#"Cannot save the cache file "{}": {:#}"#, path.display(), err);
+        }
+    }
+    let changefreq = |url: &Url| ChangefreqRule::find(&other_options.changefreq_rules, url)
+    .or_else(|| history.as_ref().and_then(|history| history.changefreq(url)))
+    .map(|changefreq| changefreq.to_string());
+
+    let now = Utc::now();
+    let mut previous_entries = other_options.previous_entries;
+    let mut entries: Vec<SitemapEntry> = sites.into_iter().map(|site| {
+        // The pages which haven't changed since the sitemap being updated keep their entry
+        let unchanged = pages.get(&site).is_some_and(|page| page.status == StatusCode::NOT_MODIFIED);
+        if let Some(previous) = unchanged.then(|| previous_entries.remove(&*site)).flatten() {
+            return SitemapEntry {
+                changefreq: changefreq(&site).or(previous.changefreq),
+                ..previous
+            };
+        }
+        let entry = SitemapEntry::new((*site).clone());
+        match pages.get(&site) {
+            Some(page) => SitemapEntry {
+                lastmod: page.last_modified.map(|last_modified| last_modified.to_rfc3339_opts(SecondsFormat::Secs, true)),
+                images: page.images.clone(),
+                videos: page.videos.clone(),
+                news: other_options.publication.as_ref().and_then(|publication| news_of(&site, page.article.clone()?, publication, now)),
+                alternates: alternates.remove(&*site).unwrap_or_default(),
+                changefreq: changefreq(&site),
+                priority: priorities.get(&site).map(|priority| format!("{:.1}", priority)),
+                ..entry
+            },
+            None => SitemapEntry {
+                changefreq: changefreq(&site),
+                ..entry
+            },
+        }
+    }).collect();
+    entries.extend(additional_links.into_iter().map(|link| SitemapEntry {
+        changefreq: changefreq(&link),
+        ..SitemapEntry::new(link)
+    }));
+    // Every format writes the entries in this order, so identical crawls produce identical files
+    other_options.sort.sort(&mut entries, &pages, starting_points);
+
+    let partial = cancellation_handle.is_cancelled();
+    eprintln!("{}", other_options.stats.summary(entries.len(), partial));
+    CrawlOutput {
+        sitemap: Sitemap {
+            kind: SitemapKind::UrlSet,
+            entries,
+        },
+        pages,
+        partial,
+    }
+}
+
+/// Adds the Last-Modified values of the crawl to the history file. Returns `None` if the file cannot be used.
+/// The history of a cancelled crawl isn't saved, since the pages which haven't been analyzed would look unchanged
+fn update_history(path: &Path, pages: &HashMap<Arc<Url>, PageInfo>, cancelled: bool) -> Option<LastmodHistory> {
+    let mut history = if path.exists() {
+        match LastmodHistory::load(path) {
+            Ok(history) => history,
+            Err(err) => {
+                eprintln!("{:#}", err);
+                return None;
+            },
+        }
+    } else {
+        LastmodHistory::default()
+    };
+    history.record(pages);
+    if cancelled {
+        return Some(history);
+    }
+    if let Err(err) = history.save(path) {
+        eprintln!(r#"Cannot save the history file "{}": {:#}"#, path.display(), err);
+    }
+    Some(history)
+}
+
+/// The news entry of an article, if it has been published recently. The title of the article is its headline,
+/// or else the `<title>` of its page
+fn news_of(url: &Url, article: Article, publication: &Publication, now: DateTime<Utc>) -> Option<News> {
+    if now.signed_duration_since(article.publication_date) > chrono::Duration::hours(MAX_NEWS_AGE_HOURS) {
+        return None;
+    }
+    let Some(language) = article.language.or_else(|| publication.language.clone()) else {
+        eprintln!(r#"The article "{}" is left out: its language cannot be detected, provide it with --publication-language"#, url);
+        return None;
+    };
+    let Some(title) = article.title else {
+        eprintln!(r#"The article "{}" is left out: it has neither a headline nor a title"#, url);
+        return None;
+    };
+    Some(News {
+        publication_name: publication.name.clone(),
+        language,
+        publication_date: article.publication_date.to_rfc3339_opts(SecondsFormat::Secs, true),
+        title,
+    })
+}
+
+fn write_sitemap(crawl_output: &CrawlOutput, output: &OutputOptions) -> anyhow::Result<()> {
+    let sitemap = &crawl_output.sitemap;
+    let mut writer = commands::create_output(output.path.as_deref())?;
+    match output.format {
+        OutputFormat::Plain => {
+            for entry in &sitemap.entries {
+                writeln!(writer, "{}", entry.loc)?;
+            }
+        },
+        OutputFormat::Txt => {
+            let path = output.path.as_deref().expect("the output file is checked for the txt format");
+            write_text_sitemaps(sitemap, path, &mut writer)?;
+        },
+        OutputFormat::Xml => sitemap.write(&mut writer)?,
+        OutputFormat::News => Sitemap {
+            kind: sitemap.kind,
+            entries: sitemap.entries.iter().filter(|entry| entry.news.is_some()).cloned().collect(),
+        }.write(&mut writer)?,
+        OutputFormat::Html => sitemap.entries.iter()
+        .map(|entry| (entry.loc.clone(), crawl_output.pages.get(&entry.loc).and_then(|page| page.title.clone())))
+        .collect::<HtmlSitemap>()
+        .write(&output.html_template, &mut writer)?,
+        OutputFormat::Json => records::write_json(records(crawl_output), &mut writer)?,
+        OutputFormat::Csv => records::write_csv(records(crawl_output), &mut writer)?,
+        OutputFormat::Atom | OutputFormat::Rss => {
+            let settings = output.feed.as_ref().expect("the feed settings are set for the feed formats");
+            let feed = Feed::from_pages(settings.title.clone(), settings.link.clone(), &crawl_output.pages, settings.size);
+            if output.format == OutputFormat::Atom {
+                feed.write_atom(&mut writer)?;
+            } else {
+                feed.write_rss(&mut writer)?;
+            }
+        },
+        OutputFormat::Ndjson => {
+            for record in records(crawl_output) {
+                serde_json::to_writer(&mut writer, &record)?;
+                writeln!(writer)?;
+            }
+        },
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Writes the URLs to `writer`, the file at `path`. When the limits of the protocol are exceeded, the next files are named
+/// adding a number to the name, like sitemap-2.txt. The numbered files left by a previous bigger sitemap are removed.
+fn write_text_sitemaps(sitemap: &Sitemap, path: &Path, writer: &mut impl Write) -> anyhow::Result<()> {
+    let parts = sitemap.text_parts();
+    writer.write_all(parts[0].as_bytes())?;
+    for (index, part) in parts.iter().enumerate().skip(1) {
+        let path = numbered_path(path, index + 1);
+        std::fs::write(&path, part).with_context(|| format!(r#"Cannot write "{}""#, path.display()))?;
+    }
+    if parts.len() > 1 {
+        eprintln!("The sitemap has been split into {} files", parts.len());
+    }
+
+    for number in parts.len() + 1.. {
+        let stale = numbered_path(path, number);
+        if !stale.is_file() {
+            break;
+        }
+        std::fs::remove_file(&stale).with_context(|| format!(r#"Cannot remove the outdated file "{}""#, stale.display()))?;
+        eprintln!(r#"Removed the outdated file "{}""#, stale.display());
+    }
+    Ok(())
+}
+
+/// Adds `-number` to the name of the file, before the extension
+fn numbered_path(path: &Path, number: usize) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(extension) => format!("{}-{}.{}", stem, number, extension.to_string_lossy()),
+        None => format!("{}-{}", stem, number),
+    };
+    path.with_file_name(name)
+}
+
+/// The records of the pages of the sitemap, in the same order, followed by the error pages and the redirects
+/// which aren't in the sitemap, sorted by URL
+fn records(crawl_output: &CrawlOutput) -> impl Iterator<Item=Record<'_>> {
+    let listed: HashSet<&Url> = crawl_output.sitemap.entries.iter().map(|entry| &entry.loc).collect();
+    let mut others: Vec<(&Arc<Url>, &PageInfo)> = crawl_output.pages.iter()
+    .filter(|(url, page)| page.is_error_or_redirect() && !listed.contains(&***url))
+    .collect();
+    others.sort_unstable_by_key(|(url, _)| *url);
+
+    crawl_output.sitemap.entries.iter()
+    .filter_map(|entry| Some(Record::new(&entry.loc, crawl_output.pages.get(&entry.loc)?)))
+    .chain(others.into_iter().map(|(url, page)| Record::new(url, page)))
+}
+
+async fn cancel_on_signal(cancellation_handle: CancellationHandle) {
+    shutdown_signal().await;
+    eprintln!("Stopping the crawl, press Ctrl-C again to exit immediately");
+    cancellation_handle.cancel();
+
+    shutdown_signal().await;
+    std::process::exit(INTERRUPTED_EXIT_CODE);
+}
+
+/// Completes on SIGINT or SIGTERM
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut terminate = signal(SignalKind::terminate()).expect("Failed registering the SIGTERM handler");
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {},
+            _ = terminate.recv() => {},
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbered_paths() {
+        assert_eq!(numbered_path(Path::new("out/sitemap.txt"), 2), Path::new("out/sitemap-2.txt"));
+        assert_eq!(numbered_path(Path::new("sitemap"), 3), Path::new("sitemap-3"));
+    }
+
+    #[test]
+    fn outdated_text_sitemaps() {
+        let root = std::env::temp_dir().join(format!("sitemap_generator_txt_{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let path = root.join("sitemap.txt");
+        for number in [2, 3, 5] {
+            std::fs::write(numbered_path(&path, number), "https://example.com/old\n").unwrap();
+        }
+
+        let sitemap = Sitemap {
+            kind: SitemapKind::UrlSet,
+            entries: vec![SitemapEntry::new(Url::parse("https://example.com/").unwrap())],
+        };
+        let mut writer = Vec::new();
+        write_text_sitemaps(&sitemap, &path, &mut writer).unwrap();
+        assert_eq!(writer, b"https://example.com/\n");
+        // Only the files following the last part are removed
+        assert!(!numbered_path(&path, 2).exists() && !numbered_path(&path, 3).exists());
+        assert!(numbered_path(&path, 5).exists());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
 }
@@ -0,0 +1,238 @@
+use std::fmt::{Debug, Formatter};
+use std::path::Path;
+use std::sync::Arc;
+
+use reqwest::StatusCode;
+use url::Url;
+
+use crate::{CrawlError, PageInfo};
+
+/// Receives notifications about the progress of a crawl.
+///
+/// Every method does nothing by default. Methods are called from the crawl tasks, so they should return quickly.
+/// Observers are registered with [`OptionsBuilder::add_observer`](crate::OptionsBuilder::add_observer).
+pub trait CrawlObserver: Send + Sync {
+    /// A request is about to be made
+    fn on_request_start(&self, _url: &Url) {}
+
+    /// A request has been completed, successfully or not. `status` is `None` if no response has been received,
+    /// and `body_size` is zero if the body hasn't been downloaded
+    fn on_request_finish(&self, _url: &Url, _status: Option<StatusCode>, _body_size: usize) {}
+
+    fn on_redirect(&self, _from: &Url, _to: &Url, _status: StatusCode) {}
+
+    fn on_error(&self, _url: &Url, _error: &CrawlError) {}
+
+    /// A site has been added to the sitemap
+    fn on_page_accepted(&self, _url: &Url) {}
+
+    /// A site has been excluded from the sitemap
+    fn on_page_rejected(&self, _url: &Url, _reason: RejectionReason) {}
+
+    /// A site has been scheduled for analysis
+    fn on_page_queued(&self, _url: &Url) {}
+
+    /// The analysis of a queued site has ended, successfully or not
+    fn on_page_done(&self, _url: &Url) {}
+
+    /// A page has been fetched and analyzed. It can still be removed from the sitemap later,
+    /// when another page permanently redirects to it
+    fn on_page_analyzed(&self, _url: &Url, _page: &PageInfo) {}
+
+    /// The state of the crawl couldn't be saved to `path`
+    fn on_checkpoint_failed(&self, _path: &Path, _error: &anyhow::Error) {}
+
+    /// The crawl has finished, `sites` is the number of sites found
+    fn on_crawl_finished(&self, _sites: usize) {}
+}
+
+/// Allows to keep a reference to a registered observer
+impl<T: CrawlObserver + ?Sized> CrawlObserver for Arc<T> {
+    fn on_request_start(&self, url: &Url) {
+        (**self).on_request_start(url)
+    }
+
+    fn on_request_finish(&self, url: &Url, status: Option<StatusCode>, body_size: usize) {
+        (**self).on_request_finish(url, status, body_size)
+    }
+
+    fn on_redirect(&self, from: &Url, to: &Url, status: StatusCode) {
+        (**self).on_redirect(from, to, status)
+    }
+
+    fn on_error(&self, url: &Url, error: &CrawlError) {
+        (**self).on_error(url, error)
+    }
+
+    fn on_page_accepted(&self, url: &Url) {
+        (**self).on_page_accepted(url)
+    }
+
+    fn on_page_rejected(&self, url: &Url, reason: RejectionReason) {
+        (**self).on_page_rejected(url, reason)
+    }
+
+    fn on_page_queued(&self, url: &Url) {
+        (**self).on_page_queued(url)
+    }
+
+    fn on_page_done(&self, url: &Url) {
+        (**self).on_page_done(url)
+    }
+
+    fn on_page_analyzed(&self, url: &Url, page: &PageInfo) {
+        (**self).on_page_analyzed(url, page)
+    }
+
+    fn on_checkpoint_failed(&self, path: &Path, error: &anyhow::Error) {
+        (**self).on_checkpoint_failed(path, error)
+    }
+
+    fn on_crawl_finished(&self, sites: usize) {
+        (**self).on_crawl_finished(sites)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RejectionReason {
+    /// The site isn't inside any site to analyze
+    OutOfScope,
+    /// The site has been permanently redirected to another one
+    MovedPermanently,
+    ErrorStatus(StatusCode),
+}
+
+/// The observers registered for a crawl. Notifications are forwarded to every one of them.
+#[derive(Clone, Default)]
+pub struct Observers {
+    observers: Vec<Arc<dyn CrawlObserver>>,
+}
+
+impl Observers {
+    #[inline]
+    pub(crate) fn push(&mut self, observer: Arc<dyn CrawlObserver>) {
+        self.observers.push(observer);
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.observers.is_empty()
+    }
+}
+
+impl Debug for Observers {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Observers({})", self.observers.len())
+    }
+}
+
+impl CrawlObserver for Observers {
+    fn on_request_start(&self, url: &Url) {
+        self.observers.iter().for_each(|observer| observer.on_request_start(url));
+    }
+
+    fn on_request_finish(&self, url: &Url, status: Option<StatusCode>, body_size: usize) {
+        self.observers.iter().for_each(|observer| observer.on_request_finish(url, status, body_size));
+    }
+
+    fn on_redirect(&self, from: &Url, to: &Url, status: StatusCode) {
+        self.observers.iter().for_each(|observer| observer.on_redirect(from, to, status));
+    }
+
+    fn on_error(&self, url: &Url, error: &CrawlError) {
+        self.observers.iter().for_each(|observer| observer.on_error(url, error));
+    }
+
+    fn on_page_accepted(&self, url: &Url) {
+        self.observers.iter().for_each(|observer| observer.on_page_accepted(url));
+    }
+
+    fn on_page_rejected(&self, url: &Url, reason: RejectionReason) {
+        self.observers.iter().for_each(|observer| observer.on_page_rejected(url, reason));
+    }
+
+    fn on_page_queued(&self, url: &Url) {
+        self.observers.iter().for_each(|observer| observer.on_page_queued(url));
+    }
+
+    fn on_page_done(&self, url: &Url) {
+        self.observers.iter().for_each(|observer| observer.on_page_done(url));
+    }
+
+    fn on_page_analyzed(&self, url: &Url, page: &PageInfo) {
+        self.observers.iter().for_each(|observer| observer.on_page_analyzed(url, page));
+    }
+
+    fn on_checkpoint_failed(&self, path: &Path, error: &anyhow::Error) {
+        self.observers.iter().for_each(|observer| observer.on_checkpoint_failed(path, error));
+    }
+
+    fn on_crawl_finished(&self, sites: usize) {
+        self.observers.iter().for_each(|observer| observer.on_crawl_finished(sites));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::{Fetcher, FetchResponse, MemoryFetcher, Options};
+    use crate::test_utils::{crawl, url};
+
+    #[tokio::test]
+    async fn observer_notifications() {
+        #[derive(Default)]
+        struct Recorder {
+            notifications: Mutex<Vec<String>>,
+        }
+
+        impl CrawlObserver for Recorder {
+            fn on_request_finish(&self, url: &Url, status: Option<StatusCode>, _body_size: usize) {
+                self.notifications.lock().unwrap().push(format!("finish {} {:?}", url, status.map(|status| status.as_u16())));
+            }
+
+            fn on_redirect(&self, from: &Url, to: &Url, status: StatusCode) {
+                self.notifications.lock().unwrap().push(format!("redirect {} {} {}", from, to, status.as_u16()));
+            }
+
+            fn on_page_rejected(&self, url: &Url, reason: RejectionReason) {
+                self.notifications.lock().unwrap().push(format!("rejected {} {:?}", url, reason));
+            }
+
+            fn on_crawl_finished(&self, sites: usize) {
+                self.notifications.lock().unwrap().push(format!("finished {}", sites));
+            }
+        }
+
+        /// Fails the requests to "/broken"
+        struct BrokenFetcher(MemoryFetcher);
+
+        impl Fetcher for BrokenFetcher {
+            async fn fetch(&self, url: &Url) -> anyhow::Result<FetchResponse> {
+                if url.path() == "/broken" {
+                    anyhow::bail!("connection reset");
+                }
+                self.0.fetch(url).await
+            }
+        }
+
+        let fetcher = BrokenFetcher(MemoryFetcher::new()
+        .with_redirect(url("/"), &url("/home"), StatusCode::MOVED_PERMANENTLY)
+        .with_page(url("/home"), r#"<a href="https://other.com/">Other</a> <a href="/broken">Broken</a>"#));
+
+        let recorder = Arc::new(Recorder::default());
+        let options = Options::builder().add_observer(recorder.clone()).build();
+        crawl(fetcher, options).await;
+
+        assert_eq!(*recorder.notifications.lock().unwrap(), [
+            "finish https://example.com/ Some(301)",
+            "redirect https://example.com/ https://example.com/home 301",
+            "rejected https://example.com/ MovedPermanently",
+            "finish https://example.com/home Some(200)",
+            "rejected https://other.com/ OutOfScope",
+            "finish https://example.com/broken None",
+            "finished 2",
+        ]);
+    }
+}
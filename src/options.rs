@@ -1,26 +1,46 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
 use std::sync::Arc;
-use tokio::sync::mpsc::UnboundedSender;
+use std::time::Duration;
+
 use url::Url;
 
+use crate::{CancellationHandle, CrawlObserver, CrawlState, Observers, RequestConditions, Validator};
+
 #[derive(Debug, Clone)]
 pub struct Options {
     max_task_count: usize,
     remove_query_and_fragment: bool,
     max_recursion: usize,
-    verbose: bool,
-    verbose_sender: Option<UnboundedSender<Arc<Url>>>,
+    observers: Observers,
+    state_file: Option<PathBuf>,
+    checkpoint_interval: Duration,
+    resume_state: Option<CrawlState>,
+    cancellation_handle: Option<CancellationHandle>,
+    shutdown_grace_period: Duration,
+    max_body_size: Option<usize>,
+    collect_images: bool,
+    image_validator: Validator,
+    collect_videos: bool,
+    collect_articles: bool,
+    collect_alternates: bool,
+    collect_links: bool,
+    collect_titles: bool,
+    collect_canonicals: bool,
+    collect_descriptions: bool,
+    known_pages: Arc<HashMap<Url, RequestConditions>>,
+    remove_error_pages: bool,
+    analyze_html_only: bool,
 }
 
 impl Options {
     #[inline]
-    pub fn new(max_task_count: usize, remove_query_and_fragment: bool, max_recursion: usize, verbose: bool) -> Options {
-        Options {
-            max_task_count,
-            remove_query_and_fragment,
-            max_recursion,
-            verbose,
-            verbose_sender: None,
-        }
+    pub fn new(max_task_count: usize, remove_query_and_fragment: bool, max_recursion: usize) -> Options {
+        OptionsBuilder::new()
+        .set_max_task_count(max_task_count)
+        .set_remove_query_and_fragment(remove_query_and_fragment)
+        .set_max_recursion(max_recursion)
+        .build()
     }
 
     #[inline]
@@ -44,18 +64,116 @@ impl Options {
     }
 
     #[inline]
-    pub fn verbose(&self) -> bool {
-        self.verbose
+    pub fn observers(&self) -> &Observers {
+        &self.observers
+    }
+
+    #[inline]
+    pub fn state_file(&self) -> Option<&PathBuf> {
+        self.state_file.as_ref()
+    }
+
+    #[inline]
+    pub fn checkpoint_interval(&self) -> Duration {
+        self.checkpoint_interval
+    }
+
+    #[inline]
+    pub fn resume_state(&self) -> Option<&CrawlState> {
+        self.resume_state.as_ref()
+    }
+
+    /// Sets the state to resume the crawl from, usually loaded with [`CrawlState::load`].
+    #[inline]
+    pub fn set_resume_state(&mut self, resume_state: Option<CrawlState>) {
+        self.resume_state = resume_state;
+    }
+
+    #[inline]
+    pub fn cancellation_handle(&self) -> Option<&CancellationHandle> {
+        self.cancellation_handle.as_ref()
+    }
+
+    #[inline]
+    pub fn shutdown_grace_period(&self) -> Duration {
+        self.shutdown_grace_period
     }
 
     #[inline]
-    pub fn verbose_sender(&self) -> &Option<UnboundedSender<Arc<Url>>> {
-        &self.verbose_sender
+    pub fn max_body_size(&self) -> Option<usize> {
+        self.max_body_size
     }
 
     #[inline]
-    pub fn set_verbose_sender(&mut self, verbose_sender: Option<UnboundedSender<Arc<Url>>>) {
-        self.verbose_sender = verbose_sender;
+    pub fn collect_images(&self) -> bool {
+        self.collect_images
+    }
+
+    #[inline]
+    pub fn image_validator(&self) -> &Validator {
+        &self.image_validator
+    }
+
+    #[inline]
+    pub fn collect_videos(&self) -> bool {
+        self.collect_videos
+    }
+
+    #[inline]
+    pub fn collect_articles(&self) -> bool {
+        self.collect_articles
+    }
+
+    #[inline]
+    pub fn collect_alternates(&self) -> bool {
+        self.collect_alternates
+    }
+
+    #[inline]
+    pub fn collect_links(&self) -> bool {
+        self.collect_links
+    }
+
+    #[inline]
+    pub fn collect_titles(&self) -> bool {
+        self.collect_titles
+    }
+
+    #[inline]
+    pub fn collect_canonicals(&self) -> bool {
+        self.collect_canonicals
+    }
+
+    #[inline]
+    pub fn collect_descriptions(&self) -> bool {
+        self.collect_descriptions
+    }
+
+    #[inline]
+    pub fn known_pages(&self) -> &HashMap<Url, RequestConditions> {
+        &self.known_pages
+    }
+
+    #[inline]
+    pub fn remove_error_pages(&self) -> bool {
+        self.remove_error_pages
+    }
+
+    #[inline]
+    pub fn analyze_html_only(&self) -> bool {
+        self.analyze_html_only
+    }
+
+    /// Used by [`analyze_directory`](crate::directory::analyze_directory), which reports the missing files
+    #[inline]
+    pub(crate) fn check_responses(&mut self) {
+        self.remove_error_pages = true;
+        self.analyze_html_only = true;
+    }
+
+    #[inline]
+    pub(crate) fn take_resume_state(&mut self) -> Option<CrawlState> {
+        self.resume_state.take()
     }
 }
 
@@ -66,12 +184,29 @@ impl Default for Options {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub struct OptionsBuilder {
     max_task_count: usize,
     remove_query_and_fragment: bool,
     max_recursion: usize,
-    verbose: bool,
+    observers: Observers,
+    state_file: Option<PathBuf>,
+    checkpoint_interval: Duration,
+    cancellation_handle: Option<CancellationHandle>,
+    shutdown_grace_period: Duration,
+    max_body_size: Option<usize>,
+    collect_images: bool,
+    image_validator: Validator,
+    collect_videos: bool,
+    collect_articles: bool,
+    collect_alternates: bool,
+    collect_links: bool,
+    collect_titles: bool,
+    collect_canonicals: bool,
+    collect_descriptions: bool,
+    known_pages: Arc<HashMap<Url, RequestConditions>>,
+    remove_error_pages: bool,
+    analyze_html_only: bool,
 }
 
 impl OptionsBuilder {
@@ -86,8 +221,25 @@ impl OptionsBuilder {
             max_task_count: self.max_task_count,
             remove_query_and_fragment: self.remove_query_and_fragment,
             max_recursion: self.max_recursion,
-            verbose: self.verbose,
-            verbose_sender: None,
+            observers: self.observers,
+            state_file: self.state_file,
+            checkpoint_interval: self.checkpoint_interval,
+            resume_state: None,
+            cancellation_handle: self.cancellation_handle,
+            shutdown_grace_period: self.shutdown_grace_period,
+            max_body_size: self.max_body_size,
+            collect_images: self.collect_images,
+            image_validator: self.image_validator,
+            collect_videos: self.collect_videos,
+            collect_articles: self.collect_articles,
+            collect_alternates: self.collect_alternates,
+            collect_links: self.collect_links,
+            collect_titles: self.collect_titles,
+            collect_canonicals: self.collect_canonicals,
+            collect_descriptions: self.collect_descriptions,
+            known_pages: self.known_pages,
+            remove_error_pages: self.remove_error_pages,
+            analyze_html_only: self.analyze_html_only,
         }
     }
 
@@ -109,9 +261,134 @@ impl OptionsBuilder {
         self
     }
 
+    /// Registers an observer which will be notified about the progress of the crawl
+    #[inline]
+    pub fn add_observer(mut self, observer: impl CrawlObserver + 'static) -> OptionsBuilder {
+        self.observers.push(Arc::new(observer));
+        self
+    }
+
+    /// Sets the file where the state of the crawl is periodically saved
+    #[inline]
+    pub fn set_state_file(mut self, state_file: Option<PathBuf>) -> OptionsBuilder {
+        self.state_file = state_file;
+        self
+    }
+
+    #[inline]
+    pub fn set_checkpoint_interval(mut self, checkpoint_interval: Duration) -> OptionsBuilder {
+        self.checkpoint_interval = checkpoint_interval;
+        self
+    }
+
+    /// Sets the handle which can be used to stop the crawl
+    #[inline]
+    pub fn set_cancellation_handle(mut self, cancellation_handle: Option<CancellationHandle>) -> OptionsBuilder {
+        self.cancellation_handle = cancellation_handle;
+        self
+    }
+
+    /// Sets how long the sites being analyzed when the crawl is cancelled are waited for
+    #[inline]
+    pub fn set_shutdown_grace_period(mut self, shutdown_grace_period: Duration) -> OptionsBuilder {
+        self.shutdown_grace_period = shutdown_grace_period;
+        self
+    }
+
+    /// Sets the max size in bytes of the pages to analyze, bigger pages fail with [`CrawlError::BodyTooLarge`](crate::CrawlError::BodyTooLarge)
+    #[inline]
+    pub fn set_max_body_size(mut self, max_body_size: Option<usize>) -> OptionsBuilder {
+        self.max_body_size = max_body_size;
+        self
+    }
+
+    /// Collects the images of the pages into [`PageInfo::images`](crate::PageInfo::images)
+    #[inline]
+    pub fn set_collect_images(mut self, collect_images: bool) -> OptionsBuilder {
+        self.collect_images = collect_images;
+        self
+    }
+
+    /// Sets the sites whose images are collected even though they are outside the sites to analyze, like a CDN
     #[inline]
-    pub fn set_verbose(mut self, verbose: bool) -> OptionsBuilder {
-        self.verbose = verbose;
+    pub fn set_image_validator(mut self, image_validator: Validator) -> OptionsBuilder {
+        self.image_validator = image_validator;
+        self
+    }
+
+    /// Collects the `<video>` elements and the `VideoObject`s of the pages into [`PageInfo::videos`](crate::PageInfo::videos)
+    #[inline]
+    pub fn set_collect_videos(mut self, collect_videos: bool) -> OptionsBuilder {
+        self.collect_videos = collect_videos;
+        self
+    }
+
+    /// Collects the publication date, the title and the language of articles into [`PageInfo::article`](crate::PageInfo::article)
+    #[inline]
+    pub fn set_collect_articles(mut self, collect_articles: bool) -> OptionsBuilder {
+        self.collect_articles = collect_articles;
+        self
+    }
+
+    /// Collects the language variants declared with `<link rel="alternate" hreflang>` and `Link` headers
+    /// into [`PageInfo::alternates`](crate::PageInfo::alternates), see [`hreflang_groups`](crate::hreflang_groups)
+    #[inline]
+    pub fn set_collect_alternates(mut self, collect_alternates: bool) -> OptionsBuilder {
+        self.collect_alternates = collect_alternates;
+        self
+    }
+
+    /// Collects the links of the pages into [`PageInfo::links`](crate::PageInfo::links), see [`page_rank`](crate::page_rank)
+    ///
+    /// The anchor texts are only read when this is enabled.
+    /// Otherwise the links are only kept during the crawl, to compute [`PageInfo::depth`](crate::PageInfo::depth).
+    #[inline]
+    pub fn set_collect_links(mut self, collect_links: bool) -> OptionsBuilder {
+        self.collect_links = collect_links;
+        self
+    }
+
+    /// Collects the `<title>` of the pages into [`PageInfo::title`](crate::PageInfo::title)
+    #[inline]
+    pub fn set_collect_titles(mut self, collect_titles: bool) -> OptionsBuilder {
+        self.collect_titles = collect_titles;
+        self
+    }
+
+    /// Collects the canonical URL of the pages into [`PageInfo::canonical`](crate::PageInfo::canonical)
+    #[inline]
+    pub fn set_collect_canonicals(mut self, collect_canonicals: bool) -> OptionsBuilder {
+        self.collect_canonicals = collect_canonicals;
+        self
+    }
+
+    /// Collects the meta description of the pages into [`PageInfo::description`](crate::PageInfo::description)
+    #[inline]
+    pub fn set_collect_descriptions(mut self, collect_descriptions: bool) -> OptionsBuilder {
+        self.collect_descriptions = collect_descriptions;
+        self
+    }
+
+    /// Sets the pages known from a previous crawl, which are requested with [`Fetcher::fetch_conditional`](crate::Fetcher::fetch_conditional).
+    /// The pages which haven't changed get the status `304 Not Modified` and aren't analyzed, so their links aren't followed.
+    #[inline]
+    pub fn set_known_pages(mut self, known_pages: HashMap<Url, RequestConditions>) -> OptionsBuilder {
+        self.known_pages = Arc::new(known_pages);
+        self
+    }
+
+    /// Removes the sites responding with an error status from the sitemap instead of analyzing them like the other pages,
+    /// reporting them as [`CrawlError::HttpStatus`](crate::CrawlError::HttpStatus)
+    #[inline]
+    pub fn set_remove_error_pages(mut self, remove_error_pages: bool) -> OptionsBuilder {
+        self.remove_error_pages = remove_error_pages;
+        self
+    }
+
+    /// Only downloads and analyzes the responses whose `Content-Type` is HTML. The other sites are still listed in the sitemap.
+    #[inline]
+    pub fn set_analyze_html_only(mut self, analyze_html_only: bool) -> OptionsBuilder {
+        self.analyze_html_only = analyze_html_only;
         self
     }
 
@@ -131,8 +408,93 @@ impl OptionsBuilder {
     }
 
     #[inline]
-    pub fn verbose(&self) -> bool {
-        self.verbose
+    pub fn observers(&self) -> &Observers {
+        &self.observers
+    }
+
+    #[inline]
+    pub fn state_file(&self) -> Option<&PathBuf> {
+        self.state_file.as_ref()
+    }
+
+    #[inline]
+    pub fn checkpoint_interval(&self) -> Duration {
+        self.checkpoint_interval
+    }
+
+    #[inline]
+    pub fn cancellation_handle(&self) -> Option<&CancellationHandle> {
+        self.cancellation_handle.as_ref()
+    }
+
+    #[inline]
+    pub fn shutdown_grace_period(&self) -> Duration {
+        self.shutdown_grace_period
+    }
+
+    #[inline]
+    pub fn max_body_size(&self) -> Option<usize> {
+        self.max_body_size
+    }
+
+    #[inline]
+    pub fn collect_images(&self) -> bool {
+        self.collect_images
+    }
+
+    #[inline]
+    pub fn image_validator(&self) -> &Validator {
+        &self.image_validator
+    }
+
+    #[inline]
+    pub fn collect_videos(&self) -> bool {
+        self.collect_videos
+    }
+
+    #[inline]
+    pub fn collect_articles(&self) -> bool {
+        self.collect_articles
+    }
+
+    #[inline]
+    pub fn collect_alternates(&self) -> bool {
+        self.collect_alternates
+    }
+
+    #[inline]
+    pub fn collect_links(&self) -> bool {
+        self.collect_links
+    }
+
+    #[inline]
+    pub fn collect_titles(&self) -> bool {
+        self.collect_titles
+    }
+
+    #[inline]
+    pub fn collect_canonicals(&self) -> bool {
+        self.collect_canonicals
+    }
+
+    #[inline]
+    pub fn collect_descriptions(&self) -> bool {
+        self.collect_descriptions
+    }
+
+    #[inline]
+    pub fn known_pages(&self) -> &HashMap<Url, RequestConditions> {
+        &self.known_pages
+    }
+
+    #[inline]
+    pub fn remove_error_pages(&self) -> bool {
+        self.remove_error_pages
+    }
+
+    #[inline]
+    pub fn analyze_html_only(&self) -> bool {
+        self.analyze_html_only
     }
 }
 
@@ -143,7 +505,24 @@ impl Default for OptionsBuilder {
             max_task_count: num_cpus::get(),
             remove_query_and_fragment: false,
             max_recursion: 50,
-            verbose: false,
+            observers: Observers::default(),
+            state_file: None,
+            checkpoint_interval: Duration::from_secs(30),
+            cancellation_handle: None,
+            shutdown_grace_period: Duration::from_secs(10),
+            max_body_size: None,
+            collect_images: false,
+            image_validator: Validator::new(std::iter::empty()),
+            collect_videos: false,
+            collect_articles: false,
+            collect_alternates: false,
+            collect_links: false,
+            collect_titles: false,
+            collect_canonicals: false,
+            collect_descriptions: false,
+            known_pages: Arc::new(HashMap::new()),
+            remove_error_pages: false,
+            analyze_html_only: false,
         }
     }
 }
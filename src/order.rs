@@ -0,0 +1,163 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::{PageInfo, SitemapEntry};
+
+/// How the entries of a sitemap are sorted, see [`SortOrder::sort`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    /// By URL
+    #[default]
+    Lexicographic,
+    /// The pages closer to the starting points first, then by URL
+    Depth,
+    /// In the order a breadth-first crawl finds them, see [`discovery_order`]
+    Discovery,
+}
+
+/// The position of a site in the traversal of [`discovery_order`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Discovery {
+    /// The number of links followed from a starting point
+    pub depth: usize,
+    /// Starting from zero
+    pub index: usize,
+}
+
+impl SortOrder {
+    #[inline]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SortOrder::Lexicographic => "lexicographic",
+            SortOrder::Depth => "depth",
+            SortOrder::Discovery => "discovery",
+        }
+    }
+
+    /// Sorts the entries. The depth and discovery orders use the links collected by the crawl
+    /// (see [`OptionsBuilder::set_collect_links`](crate::OptionsBuilder::set_collect_links)), so they don't depend on
+    /// the order in which the concurrent requests complete. The entries which cannot be reached from `starting_points`
+    /// come last, sorted by URL.
+    pub fn sort(self, entries: &mut [SitemapEntry], pages: &HashMap<Arc<Url>, PageInfo>, starting_points: impl IntoIterator<Item=Url>) {
+        if self == SortOrder::Lexicographic {
+            entries.sort_by(|entry, other| entry.loc.cmp(&other.loc));
+            return;
+        }
+        let discovered = discovery_order(pages, starting_points);
+        let position = |url: &Url| discovered.get(url).map_or(usize::MAX, |discovery| match self {
+            SortOrder::Depth => discovery.depth,
+            _ => discovery.index,
+        });
+        entries.sort_by(|entry, other| position(&entry.loc).cmp(&position(&other.loc)).then_with(|| entry.loc.cmp(&other.loc)));
+    }
+}
+
+impl Display for SortOrder {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for SortOrder {
+    type Err = String;
+
+    fn from_str(str: &str) -> Result<Self, Self::Err> {
+        Ok(match str {
+            "lexicographic" => SortOrder::Lexicographic,
+            "depth" => SortOrder::Depth,
+            "discovery" => SortOrder::Discovery,
+            _ => return Err(format!(r#"invalid sort order "{}""#, str)),
+        })
+    }
+}
+
+/// Visits the links collected by the crawl breadth-first, starting from `starting_points` sorted by URL and following
+/// the links of every page in the order they appear. The result only depends on the content of the pages.
+/// Redirects are followed right away, their targets have the same depth.
+pub fn discovery_order(pages: &HashMap<Arc<Url>, PageInfo>, starting_points: impl IntoIterator<Item=Url>) -> HashMap<Url, Discovery> {
+    let mut starting_points: Vec<Url> = starting_points.into_iter().collect();
+    starting_points.sort();
+
+    let mut discovered: HashMap<Url, Discovery> = HashMap::new();
+    let mut queue: VecDeque<Url> = VecDeque::new();
+    for url in starting_points {
+        visit(pages, &mut discovered, &mut queue, url, 0);
+    }
+    while let Some(url) = queue.pop_front() {
+        let depth = discovered[&url].depth;
+        let Some(page) = pages.get(&url) else { continue };
+        for link in &page.links {
+            visit(pages, &mut discovered, &mut queue, link.url.clone(), depth + 1);
+        }
+    }
+    discovered
+}
+
+fn visit(pages: &HashMap<Arc<Url>, PageInfo>, discovered: &mut HashMap<Url, Discovery>, queue: &mut VecDeque<Url>, url: Url, depth: usize) {
+    let mut next = Some(url);
+    // Stops at the first visited URL, so redirect loops end too
+    while let Some(url) = next.take().filter(|url| !discovered.contains_key(url)) {
+        discovered.insert(url.clone(), Discovery { depth, index: discovered.len() });
+        next = pages.get(&url).and_then(|page| page.redirect.clone());
+        queue.push_back(url);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::StatusCode;
+
+    use super::*;
+    use crate::{MemoryFetcher, Options};
+    use crate::test_utils::{crawl, url};
+
+    #[tokio::test]
+    async fn sort_orders() {
+        let fetcher = MemoryFetcher::new()
+        .with_page(url("/"), r#"<a href="/z">Z</a> <a href="/b/">B</a>"#)
+        .with_page(url("/z"), r#"<a href="/a">A</a>"#)
+        .with_page(url("/b/"), r#"<a href="/a">A</a> <a href="/b/c">C</a>"#)
+        .with_page(url("/a"), "A")
+        .with_page(url("/b/c"), "C");
+        let options = Options::builder().set_collect_links(true).set_max_task_count(4).build();
+        let result = crawl(fetcher, options).await;
+
+        let sorted = |order: SortOrder| {
+            let mut entries: Vec<SitemapEntry> = result.sites.iter().map(|site| SitemapEntry::new((**site).clone()))
+            .chain(std::iter::once(SitemapEntry::new(url("/additional"))))
+            .collect();
+            order.sort(&mut entries, &result.pages, [url("/")]);
+            entries.into_iter().map(|entry| entry.loc.path().to_string()).collect::<Vec<_>>()
+        };
+        assert_eq!(sorted(SortOrder::Lexicographic), ["/", "/a", "/additional", "/b/", "/b/c", "/z"]);
+        assert_eq!(sorted(SortOrder::Depth), ["/", "/b/", "/z", "/a", "/b/c", "/additional"]);
+        assert_eq!(sorted(SortOrder::Discovery), ["/", "/z", "/b/", "/a", "/b/c", "/additional"]);
+        assert_eq!("depth".parse(), Ok(SortOrder::Depth));
+    }
+
+    #[tokio::test]
+    async fn redirects_and_depths() {
+        let fetcher = MemoryFetcher::new()
+        .with_page(url("/"), r#"<a href="/old">Old</a>"#)
+        .with_redirect(url("/old"), &url("/new"), StatusCode::MOVED_PERMANENTLY)
+        .with_page(url("/new"), r#"<a href="/deep">Deep</a>"#)
+        .with_page(url("/deep"), "Deep");
+
+        let result = crawl(fetcher.clone(), Options::builder().set_collect_links(true).build()).await;
+        let discovered = discovery_order(&result.pages, [url("/")]);
+        assert_eq!(discovered[&url("/new")], Discovery { depth: 1, index: 2 });
+        assert_eq!(discovered[&url("/deep")].depth, 2);
+
+        // The links are only kept to compute the depths
+        let result = crawl(fetcher, Options::default()).await;
+        assert_eq!(result.pages[&url("/deep")].depth, 2);
+        assert!(result.pages.values().all(|page| page.links.is_empty()));
+    }
+}
@@ -0,0 +1,119 @@
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+use futures::{stream, StreamExt};
+use reqwest::StatusCode;
+use url::Url;
+
+use crate::{CrawlError, CrawlObserver, Fetcher, Validator};
+use crate::utils::normalize;
+
+/// Records every site found by a crawl, including the starting points and the targets of redirects.
+///
+/// Register it with [`OptionsBuilder::add_observer`](crate::OptionsBuilder::add_observer), then use
+/// [`orphans`](DiscoveredSites::orphans) after the crawl to find the pages which aren't reachable.
+#[derive(Debug, Default)]
+pub struct DiscoveredSites {
+    // Without fragments, which don't identify different pages
+    sites: Mutex<HashSet<Url>>,
+}
+
+/// The result of [`DiscoveredSites::orphans`]. URLs are sorted.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OrphanReport {
+    /// Pages of the reference list which haven't been found
+    pub orphans: Vec<Url>,
+    /// Pages of the reference list which aren't inside any site to analyze, so they cannot be found
+    pub out_of_scope: Vec<Url>,
+}
+
+impl DiscoveredSites {
+    #[inline]
+    pub fn new() -> DiscoveredSites {
+        Default::default()
+    }
+
+    pub fn contains(&self, url: &Url) -> bool {
+        let url = without_fragment(url.clone());
+        let sites = self.sites.lock().unwrap();
+        if sites.contains(&url) {
+            return true;
+        }
+        // The query may have been removed, see OptionsBuilder::set_remove_query_and_fragment
+        let mut without_query = url;
+        without_query.set_query(None);
+        sites.contains(&without_query)
+    }
+
+    /// Finds the pages of `reference` which haven't been found by the crawl
+    pub fn orphans(&self, reference: impl IntoIterator<Item=Url>, validator: &Validator) -> OrphanReport {
+        let mut report = OrphanReport::default();
+        let reference: HashSet<Url> = reference.into_iter().map(normalize).collect();
+        for url in reference {
+            if !validator.is_valid(&url) {
+                report.out_of_scope.push(url);
+            } else if !self.contains(&url) {
+                report.orphans.push(url);
+            }
+        }
+        report.orphans.sort();
+        report.out_of_scope.sort();
+        report
+    }
+}
+
+impl CrawlObserver for DiscoveredSites {
+    fn on_page_accepted(&self, url: &Url) {
+        self.sites.lock().unwrap().insert(without_fragment(url.clone()));
+    }
+}
+
+/// Requests the pages to check whether they still exist, without following redirects.
+/// At most `concurrency` requests are made at the same time. Results are in the same order as `urls`.
+pub async fn check_existence<F: Fetcher>(fetcher: &F, urls: &[Url], concurrency: usize) -> Vec<(Url, Result<StatusCode, CrawlError>)> {
+    stream::iter(urls)
+    .map(|url| async move {
+        let status = fetcher.fetch(url).await
+        .map(|response| response.status)
+        .map_err(CrawlError::from_fetch_error);
+        (url.clone(), status)
+    })
+    .buffered(concurrency.max(1))
+    .collect()
+    .await
+}
+
+#[inline]
+fn without_fragment(mut url: Url) -> Url {
+    url.set_fragment(None);
+    url
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{MemoryFetcher, Options};
+    use crate::test_utils::{crawl, url};
+
+    #[tokio::test]
+    async fn orphan_pages() {
+        let fetcher = MemoryFetcher::new()
+        .with_page(url("/"), r##"<a href="/linked#top">Linked</a>"##)
+        .with_page(url("/linked"), "Linked")
+        .with_page(url("/orphan"), "Orphan");
+
+        let discovered = Arc::new(DiscoveredSites::new());
+        let options = Options::builder().add_observer(discovered.clone()).build();
+        crawl(fetcher.clone(), options).await;
+
+        let reference = ["https://example.com/linked", "https://example.com/orphan", "https://example.com/deleted", "https://other.com/"].map(url);
+        let report = discovered.orphans(reference, &Validator::new(std::iter::once(url("/"))));
+        assert_eq!(report.orphans, [url("/deleted"), url("/orphan")]);
+        assert_eq!(report.out_of_scope, [url("https://other.com/")]);
+
+        let statuses: Vec<Option<StatusCode>> = check_existence(&fetcher, &report.orphans, 2).await.into_iter().map(|(_, status)| status.ok()).collect();
+        assert_eq!(statuses, [Some(StatusCode::NOT_FOUND), Some(StatusCode::OK)]);
+    }
+}
@@ -0,0 +1,46 @@
+use std::path::Path;
+
+use indicatif::ProgressBar;
+use url::Url;
+
+use sitemap_generator::{CrawlError, CrawlObserver, RejectionReason};
+
+/// Prints errors and, when verbose, the progress of the crawl.
+///
+/// Everything is printed to stderr, so that stdout only contains the sitemap.
+pub(super) struct Printer {
+    pub(super) verbose: bool,
+    pub(super) progress_bar: Option<ProgressBar>,
+}
+
+impl Printer {
+    fn print(&self, line: String) {
+        match &self.progress_bar {
+            // Printing above the progress bar avoids breaking it
+            Some(progress_bar) => progress_bar.println(line),
+            None => eprintln!("{}", line),
+        }
+    }
+}
+
+impl CrawlObserver for Printer {
+    fn on_request_start(&self, url: &Url) {
+        if self.verbose {
+            self.print(format!("Analyzing: \"{}\"", url));
+        }
+    }
+
+    fn on_error(&self, url: &Url, error: &CrawlError) {
+        self.print(format!("An error occurred analyzing \"{}\": {}", url, error));
+    }
+
+    fn on_page_rejected(&self, url: &Url, reason: RejectionReason) {
+        if self.verbose && reason == RejectionReason::MovedPermanently {
+            self.print(format!("Removed \"{}\"", url));
+        }
+    }
+
+    fn on_checkpoint_failed(&self, path: &Path, error: &anyhow::Error) {
+        self.print(format!(r#"Cannot save the crawl state to "{}": {}"#, path.display(), error));
+    }
+}
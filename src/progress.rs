@@ -0,0 +1,117 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use indicatif::{HumanBytes, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use reqwest::StatusCode;
+use url::Url;
+
+use sitemap_generator::{CrawlError, CrawlObserver, RejectionReason};
+
+const REFRESH_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Collects the statistics of the crawl
+pub(super) struct Stats {
+    start: Instant,
+    removed: AtomicUsize,
+    queued: AtomicUsize,
+    done: AtomicUsize,
+    requests: AtomicUsize,
+    errors: AtomicUsize,
+    bytes: AtomicUsize,
+}
+
+impl Stats {
+    pub(super) fn new() -> Stats {
+        Stats {
+            start: Instant::now(),
+            removed: AtomicUsize::new(0),
+            queued: AtomicUsize::new(0),
+            done: AtomicUsize::new(0),
+            requests: AtomicUsize::new(0),
+            errors: AtomicUsize::new(0),
+            bytes: AtomicUsize::new(0),
+        }
+    }
+
+    #[inline]
+    pub(super) fn errors(&self) -> usize {
+        self.errors.load(Ordering::Relaxed)
+    }
+
+    fn requests_per_second(&self) -> f64 {
+        self.requests.load(Ordering::Relaxed) as f64 / self.start.elapsed().as_secs_f64().max(0.001)
+    }
+
+    /// The line shown next to the spinner
+    fn progress_line(&self) -> String {
+        let done = self.done.load(Ordering::Relaxed);
+        let queued = self.queued.load(Ordering::Relaxed).saturating_sub(done);
+        format!(
+            "{} pages done, {} queued, {} errors, {:.1} req/s, {}",
+            done,
+            queued,
+            self.errors(),
+            self.requests_per_second(),
+            HumanBytes(self.bytes.load(Ordering::Relaxed) as u64),
+        )
+    }
+
+    /// The final summary, `sites` is the number of sites in the sitemap
+    pub(super) fn summary(&self, sites: usize, partial: bool) -> String {
+        format!(
+            "Done! {} sites found in {:.1}s{}\n  Requests: {} ({:.1}/s), downloaded: {}\n  Pages analyzed: {}, removed from the sitemap: {}, errors: {}",
+            sites,
+            self.start.elapsed().as_secs_f64(),
+            if partial { " (partial since the crawl was interrupted)" } else { "" },
+            self.requests.load(Ordering::Relaxed),
+            self.requests_per_second(),
+            HumanBytes(self.bytes.load(Ordering::Relaxed) as u64),
+            self.done.load(Ordering::Relaxed),
+            self.removed.load(Ordering::Relaxed),
+            self.errors(),
+        )
+    }
+}
+
+impl CrawlObserver for Stats {
+    fn on_request_finish(&self, _url: &Url, _status: Option<StatusCode>, body_size: usize) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        self.bytes.fetch_add(body_size, Ordering::Relaxed);
+    }
+
+    fn on_error(&self, _url: &Url, _error: &CrawlError) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn on_page_rejected(&self, _url: &Url, reason: RejectionReason) {
+        if reason != RejectionReason::OutOfScope {
+            self.removed.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn on_page_queued(&self, _url: &Url) {
+        self.queued.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn on_page_done(&self, _url: &Url) {
+        self.done.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Creates the progress bar, drawn on stderr
+pub(super) fn progress_bar() -> ProgressBar {
+    let progress_bar = ProgressBar::with_draw_target(None, ProgressDrawTarget::stderr());
+    progress_bar.set_style(ProgressStyle::with_template("{spinner} [{elapsed}] {msg}").expect("valid template"));
+    progress_bar
+}
+
+/// Refreshes the progress bar until it's finished
+pub(super) async fn update_progress_bar(progress_bar: ProgressBar, stats: Arc<Stats>) {
+    let mut interval = tokio::time::interval(REFRESH_INTERVAL);
+    while !progress_bar.is_finished() {
+        interval.tick().await;
+        progress_bar.set_message(stats.progress_line());
+        progress_bar.tick();
+    }
+}
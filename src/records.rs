@@ -0,0 +1,213 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use anyhow::{Context, Result};
+use chrono::SecondsFormat;
+use serde::Serialize;
+use url::Url;
+
+use sitemap_generator::{CrawlObserver, PageInfo};
+
+const CSV_HEADER: [&str; 10] = ["url", "status", "content_type", "depth", "title", "canonical", "lastmod", "response_time", "size", "redirect"];
+
+/// What the json, ndjson and csv formats write about a crawled page, including error pages and redirects
+#[derive(Serialize)]
+pub(super) struct Record<'a> {
+    url: &'a Url,
+    status: u16,
+    content_type: Option<&'a str>,
+    depth: usize,
+    title: Option<&'a str>,
+    canonical: Option<&'a Url>,
+    lastmod: Option<String>,
+    /// In milliseconds
+    response_time: u128,
+    /// In bytes
+    size: Option<usize>,
+    /// The target of the redirect
+    redirect: Option<&'a Url>,
+}
+
+impl<'a> Record<'a> {
+    pub(super) fn new(url: &'a Url, page: &'a PageInfo) -> Record<'a> {
+        Record {
+            url,
+            status: page.status.as_u16(),
+            content_type: page.content_type.as_deref(),
+            depth: page.depth,
+            title: page.title.as_deref(),
+            canonical: page.canonical.as_ref(),
+            lastmod: page.last_modified.map(|last_modified| last_modified.to_rfc3339_opts(SecondsFormat::Secs, true)),
+            response_time: page.response_time.as_millis(),
+            size: page.size,
+            redirect: page.redirect.as_ref(),
+        }
+    }
+
+    fn csv_fields(&self) -> [String; 10] {
+        let optional = |value: Option<String>| value.unwrap_or_default();
+        [
+            self.url.to_string(),
+            self.status.to_string(),
+            optional(self.content_type.map(str::to_string)),
+            self.depth.to_string(),
+            optional(self.title.map(str::to_string)),
+            optional(self.canonical.map(Url::to_string)),
+            optional(self.lastmod.clone()),
+            self.response_time.to_string(),
+            optional(self.size.map(|size| size.to_string())),
+            optional(self.redirect.map(Url::to_string)),
+        ]
+    }
+}
+
+/// Writes the records as a JSON array
+pub(super) fn write_json<'a>(records: impl Iterator<Item=Record<'a>>, mut writer: impl Write) -> Result<()> {
+    serde_json::to_writer_pretty(&mut writer, &records.collect::<Vec<_>>())?;
+    writeln!(writer)?;
+    Ok(())
+}
+
+/// Writes the records as CSV, with a header
+pub(super) fn write_csv<'a>(records: impl Iterator<Item=Record<'a>>, mut writer: impl Write) -> Result<()> {
+    write_csv_line(&mut writer, CSV_HEADER)?;
+    for record in records {
+        write_csv_line(&mut writer, record.csv_fields())?;
+    }
+    Ok(())
+}
+
+fn write_csv_line<T: AsRef<str>>(writer: &mut impl Write, fields: [T; 10]) -> Result<()> {
+    let fields: Vec<String> = fields.iter().map(|field| {
+        let field = field.as_ref();
+        if field.contains([',', '"', '\n', '\r']) {
+            format!(r#""{}""#, field.replace('"', r#""""#))
+        } else {
+            field.to_string()
+        }
+    }).collect();
+    // Line endings of RFC 4180
+    write!(writer, "{}\r\n", fields.join(","))?;
+    Ok(())
+}
+
+/// Writes a line of NDJSON as soon as every page is analyzed.
+///
+/// Pages which are later removed from the sitemap, like the targets of permanent redirects, are written too.
+pub(super) struct NdjsonWriter {
+    /// Stdout if it's `None`
+    path: Option<PathBuf>,
+    /// Opened when the first line is written, so that an existing file isn't truncated before the crawl starts
+    writer: Mutex<Option<Box<dyn Write + Send>>>,
+    /// The first error, after which nothing else is written
+    error: Mutex<Option<anyhow::Error>>,
+}
+
+impl NdjsonWriter {
+    /// Writes to `path`, or to stdout if it's `None`
+    #[inline]
+    pub(super) fn new(path: Option<PathBuf>) -> NdjsonWriter {
+        NdjsonWriter {
+            path,
+            writer: Mutex::new(None),
+            error: Mutex::new(None),
+        }
+    }
+
+    /// Flushes the output, returning the first error which occurred. The file is created even if no page has been written
+    pub(super) fn finish(&self) -> Result<()> {
+        if let Some(err) = self.error.lock().unwrap().take() {
+            return Err(err);
+        }
+        let mut writer = self.writer.lock().unwrap();
+        self.open(&mut writer)?.flush()?;
+        Ok(())
+    }
+
+    fn open<'a>(&self, writer: &'a mut Option<Box<dyn Write + Send>>) -> Result<&'a mut Box<dyn Write + Send>> {
+        if writer.is_none() {
+            *writer = Some(match &self.path {
+                Some(path) => Box::new(BufWriter::new(File::create(path).with_context(|| format!(r#"Cannot create "{}""#, path.display()))?)),
+                None => Box::new(std::io::stdout()),
+            });
+        }
+        Ok(writer.as_mut().expect("the writer has just been opened"))
+    }
+}
+
+impl CrawlObserver for NdjsonWriter {
+    fn on_page_analyzed(&self, url: &Url, page: &PageInfo) {
+        let mut error = self.error.lock().unwrap();
+        if error.is_some() {
+            return;
+        }
+        let mut line = serde_json::to_vec(&Record::new(url, page)).expect("records can always be serialized");
+        line.push(b'\n');
+        let mut writer = self.writer.lock().unwrap();
+        let result = self.open(&mut writer).and_then(|writer| Ok(writer.write_all(&line).and_then(|()| writer.flush())?));
+        if let Err(err) = result {
+            *error = Some(err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn page_records() {
+        use std::sync::Arc;
+
+        use reqwest::header::{CONTENT_LENGTH, CONTENT_TYPE, HeaderMap, HeaderValue};
+        use reqwest::StatusCode;
+        use sitemap_generator::{analyze_with_fetcher, MemoryFetcher, Options, Validator};
+
+        #[derive(Default)]
+        struct Recorder {
+            analyzed: Mutex<Vec<String>>,
+        }
+
+        impl CrawlObserver for Recorder {
+            fn on_page_analyzed(&self, url: &Url, _page: &PageInfo) {
+                self.analyzed.lock().unwrap().push(url.to_string());
+            }
+        }
+
+        let url = |str: &str| Url::parse(str).unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/pdf"));
+        headers.insert(CONTENT_LENGTH, HeaderValue::from_static("1234"));
+        let html = r#"<title>Home, "sweet" home</title><link rel="canonical" href="/?ref=1"><a href="/guide.pdf">Guide</a> <a href="/old">Old</a>"#;
+        let fetcher = MemoryFetcher::new()
+        .with_page(url("https://example.com/"), html)
+        .with_response(url("https://example.com/guide.pdf"), StatusCode::OK, headers, "%PDF")
+        .with_redirect(url("https://example.com/old"), &url("https://example.com/"), StatusCode::MOVED_PERMANENTLY);
+
+        let recorder = Arc::new(Recorder::default());
+        let options = Options::builder().set_collect_titles(true).set_collect_canonicals(true).set_analyze_html_only(true).add_observer(recorder.clone()).build();
+        let validator = Validator::new(std::iter::once(url("https://example.com/")));
+        let result = analyze_with_fetcher(fetcher, std::iter::once(url("https://example.com/")), validator, options).await;
+
+        let mut analyzed = recorder.analyzed.lock().unwrap().clone();
+        analyzed.sort();
+        assert_eq!(analyzed, ["https://example.com/", "https://example.com/guide.pdf"]);
+
+        let urls = [url("https://example.com/"), url("https://example.com/guide.pdf"), url("https://example.com/old")];
+        let mut csv = Vec::new();
+        write_csv(urls.iter().map(|url| Record::new(url, &result.pages[url])), &mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let lines: Vec<&str> = csv.split_terminator("\r\n").collect();
+        assert_eq!(lines[0], "url,status,content_type,depth,title,canonical,lastmod,response_time,size,redirect");
+        assert!(lines[1].starts_with(r#"https://example.com/,200,text/html; charset=utf-8,0,"Home, ""sweet"" home",https://example.com/?ref=1,,"#));
+        assert!(lines[1].ends_with(&format!(",{},", html.len())));
+        assert!(lines[2].starts_with("https://example.com/guide.pdf,200,application/pdf,1,,,,"));
+        assert!(lines[2].ends_with(",1234,"));
+        // The redirect isn't in the sitemap, but its page is kept
+        assert!(!result.sites.contains(&url("https://example.com/old")));
+        assert!(lines[3].starts_with("https://example.com/old,301,"));
+        assert!(lines[3].ends_with(",https://example.com/"));
+    }
+}
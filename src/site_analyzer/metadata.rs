@@ -0,0 +1,386 @@
+use chrono::{DateTime, FixedOffset, NaiveDateTime, TimeZone};
+use lazy_static::lazy_static;
+use scraper::{ElementRef, Html, Selector};
+use serde_json::{Map, Value};
+use url::Url;
+
+use crate::Article;
+use crate::sitemap::{Alternate, Video};
+use crate::utils::{filter_http, normalize};
+use crate::validation::parse_w3c_datetime;
+
+lazy_static! {
+    static ref TITLE_SELECTOR: Selector = Selector::parse("title").unwrap();
+    static ref CANONICAL_SELECTOR: Selector = Selector::parse(r#"link[rel~="canonical"][href]"#).unwrap();
+    static ref DESCRIPTION_SELECTOR: Selector = Selector::parse(r#"meta[name="description"]"#).unwrap();
+    static ref JSON_LD_SELECTOR: Selector = Selector::parse(r#"script[type="application/ld+json"]"#).unwrap();
+    static ref VIDEO_SELECTOR: Selector = Selector::parse("video").unwrap();
+    static ref SOURCE_SELECTOR: Selector = Selector::parse("source").unwrap();
+    static ref IFRAME_SELECTOR: Selector = Selector::parse("iframe[src]").unwrap();
+    static ref PUBLISHED_TIME_SELECTOR: Selector = Selector::parse(r#"meta[property="article:published_time"]"#).unwrap();
+    static ref OG_TITLE_SELECTOR: Selector = Selector::parse(r#"meta[property="og:title"]"#).unwrap();
+    static ref TIME_SELECTOR: Selector = Selector::parse("article time[datetime]").unwrap();
+    static ref HTML_SELECTOR: Selector = Selector::parse("html[lang]").unwrap();
+    static ref IMG_SELECTOR: Selector = Selector::parse("img[alt]").unwrap();
+    static ref ALTERNATE_SELECTOR: Selector = Selector::parse(r#"link[rel~="alternate"][hreflang][href]"#).unwrap();
+}
+
+/// The content of the `<title>` element
+pub fn page_title(html: &Html) -> Option<String> {
+    html.select(&TITLE_SELECTOR).next().and_then(|title| clean_text(&title.text().collect::<String>()))
+}
+
+/// The URL of `<link rel="canonical">`
+pub fn canonical(html: &Html, base_url: &Url) -> Option<Url> {
+    let href = html.select(&CANONICAL_SELECTOR).next()?.value().attr("href")?;
+    base_url.join(href.trim()).ok().filter(filter_http).map(normalize)
+}
+
+/// The content of `<meta name="description">`
+pub fn meta_description(html: &Html) -> Option<String> {
+    html.select(&DESCRIPTION_SELECTOR).next().and_then(|meta| meta.value().attr("content")).and_then(clean_text)
+}
+
+/// The JSON-LD scripts of the page. Invalid scripts are ignored
+pub fn json_ld(html: &Html) -> Vec<Value> {
+    html.select(&JSON_LD_SELECTOR)
+    .filter_map(|script| serde_json::from_str(&script.text().collect::<String>()).ok())
+    .collect()
+}
+
+/// Finds the schema.org objects of type `type_name`, also inside `@graph` arrays and other objects
+pub fn objects_of_type<'a>(values: &'a [Value], type_name: &str) -> Vec<&'a Map<String, Value>> {
+    fn visit<'a>(value: &'a Value, type_name: &str, found: &mut Vec<&'a Map<String, Value>>) {
+        match value {
+            Value::Array(values) => values.iter().for_each(|value| visit(value, type_name, found)),
+            Value::Object(object) => {
+                if object.get("@type").is_some_and(|types| has_type(types, type_name)) {
+                    found.push(object);
+                }
+                object.values().for_each(|value| visit(value, type_name, found));
+            },
+            _ => {},
+        }
+    }
+
+    let mut found = Vec::new();
+    values.iter().for_each(|value| visit(value, type_name, &mut found));
+    found
+}
+
+/// Collects the `<video>` elements, the YouTube and Vimeo players embedded with `<iframe>`
+/// and the `VideoObject`s of the JSON-LD scripts. Missing titles and descriptions are taken from the page.
+pub fn collect_videos(html: &Html, base_url: &Url) -> Vec<Video> {
+    let json_ld = json_ld(html);
+    let mut videos: Vec<Video> = objects_of_type(&json_ld, "VideoObject").into_iter().map(|object| Video {
+        thumbnail_loc: object.get("thumbnailUrl").and_then(|value| json_url(value, base_url)),
+        title: object.get("name").and_then(json_text),
+        description: object.get("description").and_then(json_text),
+        content_loc: object.get("contentUrl").and_then(|value| json_url(value, base_url)),
+        player_loc: object.get("embedUrl").and_then(|value| json_url(value, base_url)),
+        duration: object.get("duration").and_then(Value::as_str).and_then(parse_duration),
+    }).collect();
+
+    for element in html.select(&VIDEO_SELECTOR) {
+        let content_loc = element.value().attr("src")
+        .or_else(|| element.select(&SOURCE_SELECTOR).find_map(|source| source.value().attr("src")))
+        .and_then(|src| join(base_url, src));
+        let thumbnail_loc = element.value().attr("poster").and_then(|poster| join(base_url, poster));
+        let title = element.value().attr("title").and_then(clean_text).or_else(|| text_of_caption(element));
+
+        // The same video can be described by the structured data too
+        match videos.iter_mut().find(|video| content_loc.is_some() && video.content_loc == content_loc) {
+            Some(video) => {
+                video.thumbnail_loc = video.thumbnail_loc.take().or(thumbnail_loc);
+                video.title = video.title.take().or(title);
+            },
+            None => videos.push(Video {
+                thumbnail_loc,
+                title,
+                content_loc,
+                ..Video::default()
+            }),
+        }
+    }
+
+    for element in html.select(&IFRAME_SELECTOR) {
+        let Some(player_loc) = element.value().attr("src").and_then(|src| join(base_url, src)) else { continue };
+        let Some(thumbnail_loc) = embedded_player(&player_loc) else { continue };
+        let title = element.value().attr("title").and_then(clean_text).or_else(|| text_of_caption(element));
+
+        match videos.iter_mut().find(|video| video.player_loc.as_ref() == Some(&player_loc)) {
+            Some(video) => {
+                video.thumbnail_loc = video.thumbnail_loc.take().or(thumbnail_loc);
+                video.title = video.title.take().or(title);
+            },
+            None => videos.push(Video {
+                thumbnail_loc,
+                title,
+                player_loc: Some(player_loc),
+                ..Video::default()
+            }),
+        }
+    }
+
+    if videos.iter().any(|video| video.title.is_none() || video.description.is_none()) {
+        let title = page_title(html);
+        let description = meta_description(html);
+        for video in &mut videos {
+            video.title = video.title.take().or_else(|| title.clone());
+            video.description = video.description.take().or_else(|| description.clone());
+        }
+    }
+    videos
+}
+
+/// The language variants declared with `<link rel="alternate" hreflang>`
+pub fn collect_alternates(html: &Html, base_url: &Url) -> Vec<Alternate> {
+    html.select(&ALTERNATE_SELECTOR).filter_map(|link| {
+        let hreflang = link.value().attr("hreflang")?.trim();
+        let href = join(base_url, link.value().attr("href")?)?;
+        (!hreflang.is_empty()).then(|| Alternate {
+            hreflang: hreflang.to_string(),
+            href: normalize(href),
+        })
+    }).collect()
+}
+
+/// The text of a link, or the `alt` of its images and its `title` when it has no text
+pub fn anchor_text(element: ElementRef) -> Option<String> {
+    clean_text(&element.text().collect::<String>())
+    .or_else(|| element.select(&IMG_SELECTOR).find_map(|img| img.value().attr("alt")).and_then(clean_text))
+    .or_else(|| element.value().attr("alt").or_else(|| element.value().attr("title")).and_then(clean_text))
+}
+
+/// Finds the publication date, the title and the language of an article. The publication date is read from
+/// `<meta property="article:published_time">`, a `NewsArticle` of the JSON-LD scripts or a `<time>` element of an `<article>`.
+/// Returns `None` if the page has no publication date.
+pub fn collect_article(html: &Html) -> Option<Article> {
+    let json_ld = json_ld(html);
+    let news_article = objects_of_type(&json_ld, "NewsArticle").into_iter().next();
+
+    let published = html.select(&PUBLISHED_TIME_SELECTOR).next().and_then(|meta| meta.value().attr("content"))
+    .or_else(|| news_article.and_then(|article| article.get("datePublished")).and_then(Value::as_str))
+    .or_else(|| html.select(&TIME_SELECTOR).next().and_then(|time| time.value().attr("datetime")));
+    let publication_date = published.and_then(parse_publication_date)?;
+
+    let title = news_article.and_then(|article| article.get("headline")).and_then(json_text)
+    .or_else(|| html.select(&OG_TITLE_SELECTOR).next().and_then(|meta| meta.value().attr("content")).and_then(clean_text))
+    .or_else(|| page_title(html));
+    let language = news_article.and_then(|article| article.get("inLanguage")).and_then(Value::as_str)
+    .or_else(|| html.select(&HTML_SELECTOR).next().and_then(|element| element.value().attr("lang")))
+    .and_then(news_language);
+
+    Some(Article {
+        publication_date,
+        title,
+        language,
+    })
+}
+
+/// Parses a W3C Datetime, dates without a time zone are considered to be in UTC
+fn parse_publication_date(date: &str) -> Option<DateTime<FixedOffset>> {
+    let date = date.trim();
+    parse_w3c_datetime(date)
+    .or_else(|| DateTime::parse_from_rfc3339(date).ok())
+    .or_else(|| {
+        ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M:%S"].iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(date, format).ok())
+        .and_then(|date| Some(FixedOffset::east_opt(0)?.from_utc_datetime(&date)))
+    })
+}
+
+/// Converts a language tag to the ISO 639 code used by news sitemaps, like `en` for `en-US`.
+/// Chinese keeps the script or region, as `zh-cn` or `zh-tw`
+fn news_language(tag: &str) -> Option<String> {
+    let tag = tag.trim().to_ascii_lowercase().replace('_', "-");
+    let (language, region) = tag.split_once('-').unwrap_or((&tag, ""));
+    if language.is_empty() || !language.chars().all(|char| char.is_ascii_lowercase()) {
+        return None;
+    }
+    Some(match (language, region) {
+        ("zh", "tw" | "hk" | "hant") => "zh-tw".to_string(),
+        ("zh", _) => "zh-cn".to_string(),
+        _ => language.to_string(),
+    })
+}
+
+/// Whether `url` is a YouTube or Vimeo player, returning the thumbnail of the video if it's known
+fn embedded_player(url: &Url) -> Option<Option<Url>> {
+    let host = url.host_str()?.trim_start_matches("www.");
+    let mut segments = url.path_segments()?;
+    match (host, segments.next(), segments.next()) {
+        ("youtube.com" | "youtube-nocookie.com", Some("embed"), Some(id)) if !id.is_empty() => {
+            Some(Url::parse(&format!("https://i.ytimg.com/vi/{}/hqdefault.jpg", id)).ok())
+        },
+        ("player.vimeo.com", Some("video"), Some(id)) if !id.is_empty() => Some(None),
+        _ => None,
+    }
+}
+
+/// The longest duration allowed by the video extension, 8 hours
+const MAX_VIDEO_DURATION: u32 = 28800;
+
+/// Parses an ISO 8601 duration like `PT1M30S` into seconds.
+/// Returns `None` for durations outside of the range allowed by the video extension, from 1 second to 8 hours
+pub fn parse_duration(duration: &str) -> Option<u32> {
+    let rest = duration.trim().strip_prefix('P')?;
+    let (date, time) = rest.split_once('T').unwrap_or((rest, ""));
+    if date.is_empty() && time.is_empty() {
+        return None;
+    }
+
+    fn components(str: &str, units: &[(char, f64)]) -> Option<f64> {
+        let mut seconds = 0.0;
+        let mut rest = str;
+        while !rest.is_empty() {
+            let end = rest.find(|char: char| !char.is_ascii_digit() && char != '.')?;
+            let value: f64 = rest[..end].parse().ok()?;
+            let unit = rest[end..].chars().next()?;
+            seconds += value * units.iter().find(|(name, _)| *name == unit)?.1;
+            rest = &rest[end + unit.len_utf8()..];
+        }
+        Some(seconds)
+    }
+
+    let seconds = components(date, &[('W', 604800.0), ('D', 86400.0)])? + components(time, &[('H', 3600.0), ('M', 60.0), ('S', 1.0)])?;
+    let seconds = seconds.round();
+    (1.0..=MAX_VIDEO_DURATION as f64).contains(&seconds).then_some(seconds as u32)
+}
+
+/// The caption of the `<figure>` containing the element
+fn text_of_caption(element: ElementRef) -> Option<String> {
+    let figure = element.ancestors().filter_map(ElementRef::wrap).find(|ancestor| ancestor.value().name() == "figure")?;
+    let caption = figure.children().filter_map(ElementRef::wrap).find(|child| child.value().name() == "figcaption")?;
+    clean_text(&caption.text().collect::<String>())
+}
+
+/// Whether `@type` is `type_name`, possibly among other types or as a full schema.org URL
+fn has_type(types: &Value, type_name: &str) -> bool {
+    match types {
+        Value::String(name) => name.rsplit(['/', ':']).next() == Some(type_name),
+        Value::Array(types) => types.iter().any(|types| has_type(types, type_name)),
+        _ => false,
+    }
+}
+
+/// A URL of the structured data, which can be a string, an array or an `ImageObject`
+fn json_url(value: &Value, base_url: &Url) -> Option<Url> {
+    match value {
+        Value::String(url) => join(base_url, url),
+        Value::Array(values) => values.iter().find_map(|value| json_url(value, base_url)),
+        Value::Object(object) => object.get("url").or_else(|| object.get("contentUrl")).and_then(|value| json_url(value, base_url)),
+        _ => None,
+    }
+}
+
+#[inline]
+fn json_text(value: &Value) -> Option<String> {
+    value.as_str().and_then(clean_text)
+}
+
+#[inline]
+fn join(base_url: &Url, url: &str) -> Option<Url> {
+    base_url.join(url.trim()).ok().filter(filter_http)
+}
+
+/// Collapses whitespace, returning `None` for empty texts
+fn clean_text(text: &str) -> Option<String> {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    (!text.is_empty()).then_some(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::url;
+
+    #[test]
+    fn video_metadata() {
+        let html = Html::parse_document(r#"
+        <title>Tutorials</title>
+        <meta name="description" content="Learn how to crawl">
+        <script type="application/ld+json">
+            {"@context": "https://schema.org", "@graph": [{
+                "@type": "VideoObject",
+                "name": "First steps",
+                "description": "Installing the crawler",
+                "thumbnailUrl": ["/first.jpg"],
+                "contentUrl": "https://cdn.example.com/first.mp4",
+                "duration": "PT1M30S"
+            }]}
+        </script>
+        <video src="https://cdn.example.com/first.mp4"></video>
+        <figure>
+            <video poster="/second.jpg"><source src="second.webm" type="video/webm"></video>
+            <figcaption>Second steps</figcaption>
+        </figure>
+        <iframe src="https://www.youtube-nocookie.com/embed/abc123?rel=0" title="Third steps"></iframe>
+        <iframe src="https://player.vimeo.com/video/42"></iframe>
+        <iframe src="https://maps.example.net/embed/1"></iframe>
+    "#);
+        let videos = collect_videos(&html, &url("/tutorials/"));
+        assert_eq!(videos, [Video {
+            thumbnail_loc: Some(url("/first.jpg")),
+            title: Some("First steps".to_string()),
+            description: Some("Installing the crawler".to_string()),
+            content_loc: Some(url("https://cdn.example.com/first.mp4")),
+            player_loc: None,
+            duration: Some(90),
+        }, Video {
+            thumbnail_loc: Some(url("/second.jpg")),
+            title: Some("Second steps".to_string()),
+            description: Some("Learn how to crawl".to_string()),
+            content_loc: Some(url("/tutorials/second.webm")),
+            player_loc: None,
+            duration: None,
+        }, Video {
+            thumbnail_loc: Some(url("https://i.ytimg.com/vi/abc123/hqdefault.jpg")),
+            title: Some("Third steps".to_string()),
+            description: Some("Learn how to crawl".to_string()),
+            content_loc: None,
+            player_loc: Some(url("https://www.youtube-nocookie.com/embed/abc123?rel=0")),
+            duration: None,
+        }, Video {
+            thumbnail_loc: None,
+            title: Some("Tutorials".to_string()),
+            description: Some("Learn how to crawl".to_string()),
+            content_loc: None,
+            player_loc: Some(url("https://player.vimeo.com/video/42")),
+            duration: None,
+        }]);
+
+        assert_eq!(parse_duration("PT2H"), Some(7200));
+        assert_eq!(parse_duration("PT0.5S"), Some(1));
+        assert_eq!(parse_duration("PT0S"), None);
+        assert_eq!(parse_duration("P1DT2H"), None);
+        assert_eq!(parse_duration("P"), None);
+        assert_eq!(parse_duration("1:30"), None);
+    }
+
+    #[test]
+    fn article_metadata() {
+        let html = Html::parse_document(r#"
+        <html lang="en-US">
+        <title>Blog</title>
+        <script type="application/ld+json">
+            {"@type": ["Article", "NewsArticle"], "headline": "Crawler released", "datePublished": "2022-05-02T10:00:00+02:00"}
+        </script>
+        <article><time datetime="2022-05-01">May 1st</time></article>
+    "#);
+        let article = collect_article(&html).unwrap();
+        assert_eq!(article.publication_date, DateTime::parse_from_rfc3339("2022-05-02T08:00:00Z").unwrap());
+        assert_eq!(article.title.as_deref(), Some("Crawler released"));
+        assert_eq!(article.language.as_deref(), Some("en"));
+
+        let html = Html::parse_document(r#"<html lang="zh-Hant"><title>Blog</title><article><time datetime="2022-05-01T10:00">May 1st</time></article>"#);
+        let article = collect_article(&html).unwrap();
+        assert_eq!(article.publication_date, DateTime::parse_from_rfc3339("2022-05-01T10:00:00Z").unwrap());
+        assert_eq!(article.title.as_deref(), Some("Blog"));
+        assert_eq!(article.language.as_deref(), Some("zh-tw"));
+
+        assert!(collect_article(&Html::parse_document("<title>Home</title>")).is_none());
+        // Dates outside of articles, like the ones of comments or events, are ignored
+        assert!(collect_article(&Html::parse_document(r#"<title>Events</title><p><time datetime="2022-05-01">May 1st</time></p>"#)).is_none());
+    }
+}
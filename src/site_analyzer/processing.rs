@@ -1,49 +1,80 @@
 use std::sync::Arc;
+use std::time::Instant;
 
-use anyhow::{bail, Context, Result};
+use anyhow::anyhow;
+use futures::TryStreamExt;
 use lazy_static::lazy_static;
-use reqwest::Client;
+use reqwest::header::{CONTENT_LENGTH, CONTENT_TYPE, ETAG, LAST_MODIFIED, LINK, LOCATION};
+use reqwest::StatusCode;
 use scraper::{Html, Selector};
 use tokio::sync::Semaphore;
 use tokio::task::spawn_blocking;
 use url::Url;
 
-use crate::{Options, TaskInfo, Validator};
+use crate::{Alternate, Article, CrawlError, CrawlEvent, CrawlObserver, Fetcher, FetchResponse, Link, PageInfo, RejectionReason, TaskInfo, Validator, Video};
+use crate::hreflang::parse_link_header;
+use crate::site_analyzer::metadata;
+use crate::site_analyzer::types::CrawlContext;
 use crate::utils::*;
 
 lazy_static! {
-    static ref A_SELECTOR: Selector = Selector::parse("a").unwrap();
+    static ref LINK_SELECTOR: Selector = Selector::parse("a[href], area[href]").unwrap();
     static ref BASE_SELECTOR: Selector = Selector::parse("base").unwrap();
+    static ref IMAGE_SELECTOR: Selector = Selector::parse("img, picture source").unwrap();
 }
 
 static CONNECTIONS: Semaphore = Semaphore::const_new(50); // 50 usually gives good performances
+const MAX_REDIRECTS: usize = 10;
 
-pub async fn analyze_html(task_info: &TaskInfo, client: Client, semaphore: &Semaphore, options: &Options) -> Result<Vec<Url>> {
-    if options.verbose() {
-        if let Some(tx) = options.verbose_sender() {
-            let _ = tx.send(task_info.site.clone());
-        }
-    }
+/// A page which has been analyzed
+pub struct AnalyzedPage {
+    /// The URL of the page, after redirects
+    pub url: Arc<Url>,
+    pub links: Vec<Url>,
+}
+
+/// Returns `None` if there is nothing to analyze, see [`make_request`].
+pub async fn analyze_html<F: Fetcher>(task_info: &TaskInfo, context: &CrawlContext<F>) -> Result<Option<AnalyzedPage>, CrawlError> {
+    let options = &context.options;
 
     let (html_page, site) = {
         let permit = CONNECTIONS.acquire().await;
-        let page = make_request(task_info, client).await;
+        let mut fetched = None;
+        let page = make_request(task_info, context, &mut fetched).await;
         drop(permit);
-        page?
+        // Sent without holding a connection, since a slow consumer of the events makes it wait
+        if let Some((url, status)) = fetched {
+            context.emit(|| CrawlEvent::PageFetched { url, status }).await;
+        }
+        match page? {
+            Some(Fetched::Html(html_page, site)) => (html_page, site),
+            Some(Fetched::Unchanged(site, links)) => return Ok(Some(AnalyzedPage {
+                url: site.to_arc(),
+                links,
+            })),
+            None => return Ok(None),
+        }
     };
+    let page_url = site.to_arc();
 
     let validator = task_info.validator.clone();
+    let observers = options.observers().clone();
     let remove_query_and_fragment = options.remove_query_and_fragment();
+    let image_validator = options.collect_images().then(|| options.image_validator().clone());
+    let collect_videos = options.collect_videos();
+    let collect_articles = options.collect_articles();
+    let collect_alternates = options.collect_alternates();
+    let collect_titles = options.collect_titles();
+    let collect_canonicals = options.collect_canonicals();
+    let collect_descriptions = options.collect_descriptions();
+    let collect_links = options.collect_links();
 
-    let permit = match semaphore.acquire().await {
-        Ok(permit) => permit,
-        Err(_) => bail!("cannot spawn task"),
-    };
+    let permit = context.semaphore.acquire().await.expect("the semaphore is never closed");
 
-    let links = spawn_blocking(move || {
+    let analysis = spawn_blocking(move || {
         let html = Html::parse_document(&html_page);
 
-        let base_url = html.select(&*BASE_SELECTOR)
+        let base_url = html.select(&BASE_SELECTOR)
         .filter_map(|element| element.value().attr("href"))
         .map(Url::parse)
         .filter_map(Result::ok)
@@ -54,42 +85,316 @@ pub async fn analyze_html(task_info: &TaskInfo, client: Client, semaphore: &Sema
         // Splitting this in two to make code compile
         let base_url = base_url.as_ref().unwrap_or_else(|| site.as_ref());
 
-        let iter = html.select(&*A_SELECTOR)
-        .filter_map(|a_elem| a_elem.value().attr("href"))
-        .filter_map(|link| base_url.join(link).ok())
-        .filter_http();
-
-        fn finish_collecting(iter: impl Iterator<Item=Url>, validator: &Validator) -> Vec<Url> {
-            iter.normalize()
-            .filter(|url| validator.is_valid(url))
-            .collect()
-        }
+        let title = collect_titles.then(|| metadata::page_title(&html)).flatten();
+        let description = collect_descriptions.then(|| metadata::meta_description(&html)).flatten();
+        let canonical = collect_canonicals.then(|| metadata::canonical(&html, base_url)).flatten();
+        let images = match image_validator {
+            Some(image_validator) => collect_images(&html, base_url, &validator, &image_validator),
+            None => Vec::new(),
+        };
+        let videos = if collect_videos {
+            metadata::collect_videos(&html, base_url)
+        } else {
+            Vec::new()
+        };
+        let article = collect_articles.then(|| metadata::collect_article(&html)).flatten();
+        let alternates = if collect_alternates {
+            metadata::collect_alternates(&html, base_url)
+        } else {
+            Vec::new()
+        };
 
-        if remove_query_and_fragment {
-            finish_collecting(iter.map(|mut url| {
+        let links: Vec<Link> = html.select(&LINK_SELECTOR)
+        .filter_map(|element| Some((base_url.join(element.value().attr("href")?).ok()?, element)))
+        .filter(|(url, _)| filter_http(url))
+        .map(|(mut url, element)| {
+            if remove_query_and_fragment {
                 url.set_query(None);
                 url.set_fragment(None);
-                url
-            }), &validator)
-        } else {
-            finish_collecting(iter, &validator)
-        }
+            }
+            (normalize(url), element)
+        })
+        .filter(|(url, _)| {
+            let valid = validator.is_valid(url);
+            if !valid {
+                observers.on_page_rejected(url, RejectionReason::OutOfScope);
+            }
+            valid
+        })
+        .map(|(url, element)| Link {
+            url,
+            anchor_text: collect_links.then(|| metadata::anchor_text(element)).flatten(),
+            element: element.value().name().to_string(),
+        })
+        .collect();
+        PageContent { links, title, description, canonical, images, videos, article, alternates }
     }).await;
 
     // Release semaphore
     drop(permit);
 
-    links.with_context(|| format!("Cannot analyze site {}", &task_info.site))
+    let PageContent { links, title, description, canonical, images, videos, article, alternates } = analysis.map_err(|err| CrawlError::Parse(Arc::new(anyhow!("cannot analyze the page: {}", err))))?;
+    let page = context.pages.lock().unwrap().get_mut(&page_url).and_then(|page| {
+        page.title = title;
+        page.description = description;
+        page.canonical = canonical;
+        page.images = images;
+        page.videos = videos;
+        page.article = article;
+        // The alternates of the Link headers are already present
+        for alternate in alternates {
+            if !page.alternates.contains(&alternate) {
+                page.alternates.push(alternate);
+            }
+        }
+        // Needed to compute the depths at the end of the crawl, even if the links aren't collected
+        page.links = links.clone();
+        // Only cloned when someone needs it
+        (!options.observers().is_empty()).then(|| page.clone())
+    });
+    if let Some(page) = page {
+        options.observers().on_page_analyzed(&page_url, &page);
+    }
+    let links = links.into_iter().map(|link| link.url).collect();
+    Ok(Some(AnalyzedPage {
+        url: page_url,
+        links,
+    }))
+}
+
+/// What has been extracted from the HTML of a page
+struct PageContent {
+    links: Vec<Link>,
+    title: Option<String>,
+    description: Option<String>,
+    canonical: Option<Url>,
+    images: Vec<Url>,
+    videos: Vec<Video>,
+    article: Option<Article>,
+    alternates: Vec<Alternate>,
 }
 
-async fn make_request(task_info: &TaskInfo, client: Client) -> Result<(String, UrlResult)> {
-    let response = client.get((*task_info.site).clone()).send().await?;
-    let url = if *response.url() != *task_info.site {
-        UrlResult::Url(response.url().clone())
-    } else {
-        UrlResult::Arc(task_info.site.clone())
+/// Collects the sources of `<img>` and `<picture><source>` elements, including the candidates of `srcset`.
+/// Only the images inside the sites to analyze or allowed by `image_validator` are kept.
+fn collect_images(html: &Html, base_url: &Url, validator: &Validator, image_validator: &Validator) -> Vec<Url> {
+    let mut images: Vec<Url> = Vec::new();
+    for element in html.select(&IMAGE_SELECTOR) {
+        let element = element.value();
+        let sources = element.attr("src").into_iter().chain(element.attr("srcset").into_iter().flat_map(srcset_urls));
+        for image in sources.filter_map(|source| base_url.join(source).ok()).filter_http().normalize() {
+            if (validator.is_valid(&image) || image_validator.is_valid(&image)) && !images.contains(&image) {
+                images.push(image);
+            }
+        }
+    }
+    images
+}
+
+/// Splits a `srcset` attribute into its URLs, ignoring the width and density descriptors
+fn srcset_urls(srcset: &str) -> Vec<&str> {
+    let mut urls = Vec::new();
+    let mut rest = srcset;
+    loop {
+        rest = rest.trim_start_matches(|char: char| char.is_ascii_whitespace() || char == ',');
+        if rest.is_empty() {
+            return urls;
+        }
+        let end = rest.find(|char: char| char.is_ascii_whitespace()).unwrap_or(rest.len());
+        let (url, after) = rest.split_at(end);
+        // A comma right after the URL ends the candidate, otherwise the descriptors follow until the next comma
+        match url.strip_suffix(',') {
+            Some(url) => {
+                urls.push(url.trim_end_matches(','));
+                rest = after;
+            },
+            None => {
+                urls.push(url);
+                rest = after.find(',').map_or("", |comma| &after[comma..]);
+            },
+        }
+    }
+}
+
+/// The outcome of [`make_request`]
+enum Fetched {
+    /// The body of an HTML page to analyze
+    Html(String, UrlResult),
+    /// A known page which hasn't changed, with its links from the previous crawl
+    Unchanged(UrlResult, Vec<Url>),
+}
+
+/// Requests the site, following redirects.
+/// Returns `None` if the site redirects to an already visited one or if it isn't an HTML page and only those are analyzed.
+/// Sites responding with an error status are removed from the sitemap if enabled,
+/// see [`OptionsBuilder::set_remove_error_pages`](crate::OptionsBuilder::set_remove_error_pages).
+/// The final URL and its status are put in `fetched` whatever the result, for [`CrawlEvent::PageFetched`].
+async fn make_request<F: Fetcher>(task_info: &TaskInfo, context: &CrawlContext<F>, fetched: &mut Option<(Arc<Url>, StatusCode)>) -> Result<Option<Fetched>, CrawlError> {
+    let observers = context.options.observers();
+    let mut url = UrlResult::Arc(task_info.site.clone());
+    for _ in 0..=MAX_REDIRECTS {
+        observers.on_request_start(url.as_ref());
+        let start = Instant::now();
+        let conditions = context.options.known_pages().get(url.as_ref());
+        let response = match conditions {
+            Some(conditions) => context.fetcher.fetch_conditional(url.as_ref(), conditions).await,
+            None => context.fetcher.fetch(url.as_ref()).await,
+        };
+        let response = match response {
+            Ok(response) => response,
+            Err(err) => {
+                observers.on_request_finish(url.as_ref(), None, 0);
+                return Err(CrawlError::from_fetch_error(err));
+            },
+        };
+        let status = response.status;
+        let not_modified = status == StatusCode::NOT_MODIFIED;
+        let mut page = PageInfo {
+            status,
+            content_type: response.headers.get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string),
+            last_modified: response.headers.get(LAST_MODIFIED)
+            .and_then(|value| value.to_str().ok())
+            .and_then(parse_http_date)
+            .or_else(|| not_modified.then(|| conditions?.last_modified).flatten()),
+            etag: response.headers.get(ETAG)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string)
+            .or_else(|| not_modified.then(|| conditions?.etag.clone()).flatten()),
+            response_time: start.elapsed(),
+            size: response.headers.get(CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse().ok()),
+            title: None,
+            description: None,
+            canonical: None,
+            images: Vec::new(),
+            videos: Vec::new(),
+            article: None,
+            alternates: if context.options.collect_alternates() {
+                response.headers.get_all(LINK).iter()
+                .filter_map(|value| value.to_str().ok())
+                .flat_map(|value| parse_link_header(value, &response.url))
+                .collect()
+            } else {
+                Vec::new()
+            },
+            depth: context.options.max_recursion().saturating_sub(task_info.recursion),
+            // The links of an unchanged page are the ones it had in the previous crawl
+            links: conditions.filter(|_| not_modified).map_or_else(Vec::new, |conditions| conditions.links.clone()),
+            referrer: task_info.referrer.as_deref().cloned(),
+            redirect: None,
+        };
+        let next = match redirect_location(&response) {
+            Some(next) => next.map_err(|err| CrawlError::Parse(Arc::new(err)))?,
+            None => {
+                let site = if response.url != *url.as_ref() {
+                    UrlResult::Url(response.url.clone())
+                } else {
+                    url
+                };
+                context.pages.lock().unwrap().insert(site.to_arc(), page.clone());
+                *fetched = Some((site.to_arc(), status));
+
+                if context.options.remove_error_pages() && (status.is_client_error() || status.is_server_error()) {
+                    observers.on_request_finish(site.as_ref(), Some(status), 0);
+                    context.sites.access_map(|hashset| hashset.remove(site.as_ref()));
+                    observers.on_page_rejected(site.as_ref(), RejectionReason::ErrorStatus(status));
+                    return Err(CrawlError::HttpStatus(status));
+                }
+                if not_modified {
+                    observers.on_request_finish(site.as_ref(), Some(status), 0);
+                    observers.on_page_analyzed(site.as_ref(), &page);
+                    let links = page.links.into_iter().map(|link| link.url).filter(|url| task_info.validator.is_valid(url)).collect();
+                    return Ok(Some(Fetched::Unchanged(site, links)));
+                }
+                if context.options.analyze_html_only() && !is_html(&response) {
+                    observers.on_request_finish(site.as_ref(), Some(status), 0);
+                    observers.on_page_analyzed(site.as_ref(), &page);
+                    return Ok(None);
+                }
+                let body = match read_body(response, context.options.max_body_size()).await {
+                    Ok(body) => body,
+                    Err(err) => {
+                        observers.on_request_finish(site.as_ref(), Some(status), 0);
+                        return Err(err);
+                    },
+                };
+                observers.on_request_finish(site.as_ref(), Some(status), body.len());
+                if let Some(page) = context.pages.lock().unwrap().get_mut(site.as_ref()) {
+                    page.response_time = start.elapsed();
+                    page.size = Some(body.len());
+                }
+                return Ok(Some(Fetched::Html(String::from_utf8_lossy(&body).into_owned(), site)));
+            },
+        };
+        observers.on_request_finish(url.as_ref(), Some(status), 0);
+        observers.on_redirect(url.as_ref(), &next, status);
+        // Kept to report the redirect, even if the URL isn't in the sitemap
+        page.redirect = Some(next.clone());
+        context.pages.lock().unwrap().insert(url.to_arc(), page);
+
+        if !task_info.validator.is_valid(&next) {
+            return Err(CrawlError::OutOfScopeRedirect { to: next });
+        }
+
+        let next = Arc::new(next);
+        if status == StatusCode::MOVED_PERMANENTLY || status == StatusCode::PERMANENT_REDIRECT {
+            // The old URL shouldn't end up in the sitemap, which must only list final URLs: the target is listed instead
+            context.sites.access_map(|hashset| hashset.remove(url.as_ref()));
+            observers.on_page_rejected(url.as_ref(), RejectionReason::MovedPermanently);
+        }
+
+        context.frontier.redirected(&task_info.site, next.clone());
+        if !context.sites.access_map(|hashset| hashset.insert(next.clone())) {
+            return Ok(None);
+        }
+        observers.on_page_accepted(&next);
+        url = UrlResult::Arc(next);
+    }
+    Err(CrawlError::TooManyRedirects)
+}
+
+/// Reads the whole body, failing if it's bigger than `limit`
+async fn read_body(response: FetchResponse, limit: Option<usize>) -> Result<Vec<u8>, CrawlError> {
+    let limit = match limit {
+        Some(limit) => limit,
+        None => return response.bytes().await.map_err(CrawlError::from_fetch_error),
     };
-    Ok((response.text().await?, url))
+
+    let content_length = response.headers.get(CONTENT_LENGTH)
+    .and_then(|value| value.to_str().ok())
+    .and_then(|value| value.parse::<usize>().ok());
+    if content_length.is_some_and(|length| length > limit) {
+        return Err(CrawlError::BodyTooLarge { limit });
+    }
+
+    let mut body = Vec::new();
+    let mut chunks = response.body;
+    while let Some(chunk) = chunks.try_next().await.map_err(CrawlError::from_fetch_error)? {
+        if body.len() + chunk.len() > limit {
+            return Err(CrawlError::BodyTooLarge { limit });
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body)
+}
+
+fn is_html(response: &FetchResponse) -> bool {
+    match response.headers.get(CONTENT_TYPE).and_then(|value| value.to_str().ok()) {
+        Some(content_type) => content_type.contains("html"),
+        None => true,
+    }
+}
+
+fn redirect_location(response: &FetchResponse) -> Option<anyhow::Result<Url>> {
+    if !response.status.is_redirection() {
+        return None;
+    }
+    let location = response.headers.get(LOCATION)?;
+    Some(location.to_str()
+    .map_err(|_| anyhow!("invalid Location header"))
+    .and_then(|location| Ok(response.url.join(location)?)))
 }
 
 enum UrlResult {
@@ -97,6 +402,16 @@ enum UrlResult {
     Url(Url),
 }
 
+impl UrlResult {
+    #[inline]
+    fn to_arc(&self) -> Arc<Url> {
+        match self {
+            UrlResult::Arc(arc) => arc.clone(),
+            UrlResult::Url(url) => Arc::new(url.clone()),
+        }
+    }
+}
+
 impl AsRef<Url> for UrlResult {
     #[inline]
     fn as_ref(&self) -> &Url {
@@ -106,3 +421,77 @@ impl AsRef<Url> for UrlResult {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::{HeaderMap, HeaderValue};
+
+    use crate::{MemoryFetcher, Options};
+    use crate::test_utils::{crawl, sorted_sites, url};
+
+    #[tokio::test]
+    async fn image_collection() {
+        assert_eq!(srcset_urls("a.png, b.png 2x,c.png,, d.png 100w"), ["a.png", "b.png", "c.png", "d.png"]);
+
+        let fetcher = MemoryFetcher::new().with_page(url("/"), r#"
+        <img src="/logo.png" srcset="/logo.png 1x, /logo@2x.png 2x">
+        <picture><source srcset="https://cdn.example.net/photo.webp"><img src="https://other.com/photo.jpg"></picture>
+        <img src="data:image/gif;base64,R0lGODlhAQABAAAAACw=">
+    "#);
+        let options = Options::builder()
+        .set_collect_images(true)
+        .set_image_validator(Validator::new(std::iter::once(url("https://cdn.example.net/"))))
+        .build();
+        let result = crawl(fetcher, options).await;
+        assert_eq!(result.pages[&url("/")].images, [
+            url("/logo.png"),
+            url("/logo@2x.png"),
+            url("https://cdn.example.net/photo.webp"),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn area_links() {
+        let fetcher = MemoryFetcher::new()
+        .with_page(url("/"), r#"<a href="/a">A</a> <map><area href="/map" alt="Map"></map>"#)
+        .with_page(url("/a"), "A")
+        .with_page(url("/map"), "Map");
+
+        // Collecting the links doesn't change what is crawled
+        let result = crawl(fetcher.clone(), Options::default()).await;
+        assert_eq!(sorted_sites(&result), ["https://example.com/", "https://example.com/a", "https://example.com/map"]);
+        assert!(result.pages[&url("/")].links.is_empty());
+
+        let result = crawl(fetcher, Options::builder().set_collect_links(true).build()).await;
+        assert_eq!(sorted_sites(&result), ["https://example.com/", "https://example.com/a", "https://example.com/map"]);
+        assert_eq!(result.pages[&url("/")].links[0].anchor_text.as_deref(), Some("A"));
+        assert_eq!(result.pages[&url("/")].links[1].element, "area");
+    }
+
+    #[tokio::test]
+    async fn error_pages() {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+        let fetcher = MemoryFetcher::new()
+        .with_page(url("/"), r#"<a href="/gone">Gone</a> <a href="/notes.txt">Notes</a>"#)
+        .with_response(url("/gone"), StatusCode::GONE, HeaderMap::new(), r#"<a href="/a">A</a>"#)
+        .with_response(url("/notes.txt"), StatusCode::OK, headers, r#"<a href="/b">B</a>"#)
+        .with_page(url("/a"), "A")
+        .with_page(url("/b"), "B");
+
+        // By default every response is analyzed and kept in the sitemap
+        let result = crawl(fetcher.clone(), Options::default()).await;
+        assert_eq!(sorted_sites(&result), ["https://example.com/", "https://example.com/a", "https://example.com/b", "https://example.com/gone", "https://example.com/notes.txt"]);
+        assert!(result.errors.is_empty());
+
+        let options = Options::builder().set_remove_error_pages(true).set_analyze_html_only(true).build();
+        let result = crawl(fetcher, options).await;
+        assert_eq!(sorted_sites(&result), ["https://example.com/", "https://example.com/notes.txt"]);
+        assert!(matches!(result.errors[..], [(ref site, CrawlError::HttpStatus(StatusCode::GONE))] if **site == url("/gone")));
+        // The error pages keep their information
+        let gone = &result.pages[&url("/gone")];
+        assert_eq!(gone.status, StatusCode::GONE);
+        assert_eq!(gone.referrer, Some(url("/")));
+    }
+}
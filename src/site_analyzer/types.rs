@@ -1,50 +1,122 @@
+use std::collections::HashMap;
 use std::fmt::Debug;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
-use reqwest::Client;
-use tokio::sync::mpsc::UnboundedSender;
+use tokio::sync::mpsc::{self, UnboundedSender};
 use tokio::sync::Semaphore;
 use url::Url;
 
-use crate::Options;
+use crate::{CancellationHandle, CrawlError, CrawlEvent, CrawlObserver, Fetcher, Options, PageInfo, Sites};
+use crate::checkpoint::Frontier;
 use crate::site_analyzer::processing::analyze_html;
-use crate::utils::*;
+
+/// State shared between all the tasks of a crawl
+pub struct CrawlContext<F: Fetcher> {
+    pub fetcher: F,
+    pub sites: Sites,
+    pub semaphore: Semaphore,
+    pub options: Options,
+    pub frontier: Frontier,
+    /// Cancelled when the running tasks must stop immediately
+    pub abort: CancellationHandle,
+    pub events: Option<mpsc::Sender<CrawlEvent>>,
+    /// The errors which occurred during the crawl
+    pub errors: Mutex<Vec<(Arc<Url>, CrawlError)>>,
+    /// Information about the fetched sites
+    pub pages: Mutex<HashMap<Arc<Url>, PageInfo>>,
+}
+
+impl<F: Fetcher> CrawlContext<F> {
+    /// Sends an event if someone is listening, waiting if they're behind.
+    /// The crawl is aborted when the listener goes away.
+    pub async fn emit(&self, event: impl FnOnce() -> CrawlEvent) {
+        if let Some(events) = &self.events {
+            if events.send(event()).await.is_err() {
+                self.abort.cancel();
+            }
+        }
+    }
+}
+
+/// Messages sent by the tasks to the main task
+pub enum Message {
+    /// A site to analyze
+    Task(TaskInfo),
+    /// The analysis of a site is complete.
+    /// Since it's sent after the site's links, they are already known by the main task when this is received.
+    Done(Arc<Url>),
+}
 
 pub struct TaskInfo {
     pub site: Arc<Url>,
-    pub tx: UnboundedSender<TaskInfo>,
+    pub tx: UnboundedSender<Message>,
     pub validator: Validator,
     pub recursion: usize,
+    /// The page linking to the site
+    pub referrer: Option<Arc<Url>>,
 }
 
 impl TaskInfo {
-    pub async fn spawn_task(self, client: Client, semaphore: Arc<Semaphore>, options: Arc<Options>) {
+    pub async fn spawn_task<F: Fetcher>(self, context: Arc<CrawlContext<F>>) {
         if self.recursion == 0 {
             return;
         }
 
+        context.frontier.start(self.site.clone(), self.recursion);
+        context.options.observers().on_page_queued(&self.site);
+
         tokio::spawn(async move {
-            let links = match analyze_html(&self, client, &semaphore, &options).await {
-                Ok(links) => links,
-                Err(err) => {
-                    eprintln(err, self.site.as_str()).await;
-                    return;
-                },
-            };
+            let abort = context.abort.clone();
+            tokio::select! {
+                _ = self.run(context) => {},
+                // The site remains in the frontier, since Done is never sent
+                _ = abort.cancelled() => {},
+            }
+        });
+    }
 
-            for link in links {
-                let link = Arc::new(link);
-                let start_task_info = TaskInfo {
-                    site: link.clone(),
-                    tx: self.tx.clone(),
-                    validator: self.validator.clone(),
-                    recursion: self.recursion -1,
-                };
-                if self.tx.send(start_task_info).is_err() {
-                    eprintln("Couldn't send site to main task!", link.as_str()).await;
-                }
+    /// Keeps track of the site without analyzing it, used when the crawl is being cancelled
+    pub fn postpone<F: Fetcher>(self, context: &CrawlContext<F>) {
+        if self.recursion != 0 {
+            context.frontier.start(self.site, self.recursion);
+        }
+    }
+
+    async fn run<F: Fetcher>(self, context: Arc<CrawlContext<F>>) {
+        let page = match analyze_html(&self, &context).await {
+            Ok(page) => page,
+            Err(err) => {
+                context.options.observers().on_error(&self.site, &err);
+                context.errors.lock().unwrap().push((self.site.clone(), err.clone()));
+                context.emit(|| CrawlEvent::Error {
+                    url: self.site.clone(),
+                    error: err,
+                }).await;
+                None
+            },
+        };
+        let (page_url, links) = match page {
+            Some(page) => (page.url, page.links),
+            None => (self.site.clone(), Vec::new()),
+        };
+
+        for link in links {
+            let link = Arc::new(link);
+            context.emit(|| CrawlEvent::LinkDiscovered {
+                from: page_url.clone(),
+                to: link.clone(),
+            }).await;
+            let start_task_info = TaskInfo {
+                site: link.clone(),
+                tx: self.tx.clone(),
+                validator: self.validator.clone(),
+                recursion: self.recursion -1,
+                referrer: Some(page_url.clone()),
             };
-        });
+            // The main task keeps receiving until every task has ended, so this cannot fail
+            let _ = self.tx.send(Message::Task(start_task_info));
+        };
+        let _ = self.tx.send(Message::Done(self.site.clone()));
     }
 }
 
@@ -52,20 +124,22 @@ impl TaskInfo {
 pub struct Validator {
     // Using an Arc to allow cloning
     base_urls: Arc<Vec<String>>,
+    excluded: Arc<Vec<String>>,
 }
 
 impl Validator {
     pub fn new(iter: impl Iterator<Item=Url>) -> Validator {
         Validator {
-            base_urls: Arc::new(iter
-            .map(|mut url| {
-                url.set_query(None);
-                url.set_fragment(None);
-                url
-            })
-            .filter(|url| !url.cannot_be_a_base())
-            .map(String::from)
-            .collect()),
+            base_urls: Arc::new(base_urls(iter)),
+            excluded: Arc::new(Vec::new()),
+        }
+    }
+
+    /// Rejects the URLs starting with any of `iter`, even if they are inside a site to analyze
+    pub fn excluding(self, iter: impl Iterator<Item=Url>) -> Validator {
+        Validator {
+            excluded: Arc::new(base_urls(iter)),
+            ..self
         }
     }
 
@@ -73,6 +147,18 @@ impl Validator {
         let str = url.as_str();
         self.base_urls.iter().any(|base_url| {
             str.starts_with(base_url)
-        })
+        }) && !self.excluded.iter().any(|excluded| str.starts_with(excluded))
     }
 }
+
+fn base_urls(iter: impl Iterator<Item=Url>) -> Vec<String> {
+    iter
+    .map(|mut url| {
+        url.set_query(None);
+        url.set_fragment(None);
+        url
+    })
+    .filter(|url| !url.cannot_be_a_base())
+    .map(String::from)
+    .collect()
+}
@@ -0,0 +1,773 @@
+use std::collections::HashMap;
+use std::io::Write;
+
+use anyhow::{anyhow, bail, Context, Result};
+use quick_xml::events::{BytesDecl, BytesEnd, BytesStart, BytesText, Event};
+use quick_xml::{Reader, Writer};
+use reqwest::header::LOCATION;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::Fetcher;
+use crate::validation::{MAX_SITEMAP_ENTRIES, MAX_SITEMAP_SIZE, parse_w3c_datetime};
+
+/// Max number of redirects followed downloading a sitemap
+const MAX_REDIRECTS: usize = 10;
+
+/// The namespace of the elements defined by the sitemaps.org protocol
+pub const SITEMAP_NAMESPACE: &str = "http://www.sitemaps.org/schemas/sitemap/0.9";
+/// The namespace of the [image extension](https://developers.google.com/search/docs/crawling-indexing/sitemaps/image-sitemaps)
+pub const IMAGE_NAMESPACE: &str = "http://www.google.com/schemas/sitemap-image/1.1";
+/// Max number of images listed for a single page by the image extension
+pub const MAX_IMAGES_PER_PAGE: usize = 1000;
+/// The namespace of the [video extension](https://developers.google.com/search/docs/crawling-indexing/sitemaps/video-sitemaps)
+pub const VIDEO_NAMESPACE: &str = "http://www.google.com/schemas/sitemap-video/1.1";
+/// The namespace of the [news extension](https://developers.google.com/search/docs/crawling-indexing/sitemaps/news-sitemap)
+pub const NEWS_NAMESPACE: &str = "http://www.google.com/schemas/sitemap-news/0.9";
+/// The namespace of the `<xhtml:link>` elements listing the [language variants](https://developers.google.com/search/docs/specialty/international/localized-versions#sitemap) of a page
+pub const XHTML_NAMESPACE: &str = "http://www.w3.org/1999/xhtml";
+/// News sitemaps should only list the articles published in the last 48 hours
+pub const MAX_NEWS_AGE_HOURS: i64 = 48;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SitemapKind {
+    /// A `<urlset>`, listing pages
+    UrlSet,
+    /// A `<sitemapindex>`, listing other sitemaps
+    Index,
+}
+
+impl SitemapKind {
+    #[inline]
+    fn root(self) -> &'static [u8] {
+        match self {
+            SitemapKind::UrlSet => b"urlset",
+            SitemapKind::Index => b"sitemapindex",
+        }
+    }
+
+    #[inline]
+    fn entry(self) -> &'static [u8] {
+        match self {
+            SitemapKind::UrlSet => b"url",
+            SitemapKind::Index => b"sitemap",
+        }
+    }
+}
+
+/// A `<url>` of a urlset or a `<sitemap>` of a sitemap index.
+/// `changefreq` and `priority` are always `None` and `images` is always empty for sitemap indexes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SitemapEntry {
+    pub loc: Url,
+    pub lastmod: Option<String>,
+    pub changefreq: Option<String>,
+    pub priority: Option<String>,
+    /// Written with the image extension, at most [`MAX_IMAGES_PER_PAGE`] are written
+    pub images: Vec<Url>,
+    /// Written with the video extension, incomplete videos are skipped (see [`Video::is_complete`])
+    pub videos: Vec<Video>,
+    /// Written with the news extension
+    pub news: Option<News>,
+    /// The language variants of the page, including the page itself. Written as `<xhtml:link rel="alternate">`
+    pub alternates: Vec<Alternate>,
+}
+
+/// A language variant of a page, declared with `hreflang`
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Alternate {
+    /// A language code, like `en` or `de-CH`, or `x-default`
+    pub hreflang: String,
+    pub href: Url,
+}
+
+/// A video embedded in a page, for the video extension
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Video {
+    pub thumbnail_loc: Option<Url>,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    /// The URL of the video file
+    pub content_loc: Option<Url>,
+    /// The URL of a player for the video
+    pub player_loc: Option<Url>,
+    /// In seconds
+    pub duration: Option<u32>,
+}
+
+/// A news article, for the news extension
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct News {
+    pub publication_name: String,
+    /// An ISO 639 code, like `en`
+    pub language: String,
+    /// A W3C Datetime
+    pub publication_date: String,
+    pub title: String,
+}
+
+impl Video {
+    /// Whether the video has every element required by the extension:
+    /// a thumbnail, a title, a description and either a content or a player location
+    pub fn is_complete(&self) -> bool {
+        self.thumbnail_loc.is_some() && self.title.is_some() && self.description.is_some()
+        && (self.content_loc.is_some() || self.player_loc.is_some())
+    }
+}
+
+impl SitemapEntry {
+    #[inline]
+    pub fn new(loc: Url) -> SitemapEntry {
+        SitemapEntry {
+            loc,
+            lastmod: None,
+            changefreq: None,
+            priority: None,
+            images: Vec::new(),
+            videos: Vec::new(),
+            news: None,
+            alternates: Vec::new(),
+        }
+    }
+}
+
+/// A sitemap in the XML format of the [sitemaps.org protocol](https://www.sitemaps.org/protocol.html).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sitemap {
+    pub kind: SitemapKind,
+    pub entries: Vec<SitemapEntry>,
+}
+
+impl Sitemap {
+    /// Creates a urlset containing `urls`
+    pub fn from_urls(urls: impl IntoIterator<Item=Url>) -> Sitemap {
+        Sitemap {
+            kind: SitemapKind::UrlSet,
+            entries: urls.into_iter().map(SitemapEntry::new).collect(),
+        }
+    }
+
+    /// Parses a urlset or a sitemap index. Elements of protocol extensions are ignored, except for images, videos, news and alternates.
+    pub fn parse(xml: &[u8]) -> Result<Sitemap> {
+        let mut reader = Reader::from_reader(xml);
+        reader.trim_text(true);
+
+        let mut kind = None;
+        // The names of the open elements
+        let mut path: Vec<Vec<u8>> = Vec::new();
+        let mut entries = Vec::new();
+        let mut entry: Option<PartialEntry> = None;
+        let mut buf = Vec::new();
+        loop {
+            let event = reader.read_event(&mut buf).with_context(|| format!("Invalid XML at position {}", reader.buffer_position()))?;
+            match event {
+                Event::Start(ref element) | Event::Empty(ref element) => {
+                    let name = element.name().to_vec();
+                    match (path.len(), kind) {
+                        (0, _) => {
+                            kind = Some(match &*name {
+                                b"urlset" => SitemapKind::UrlSet,
+                                b"sitemapindex" => SitemapKind::Index,
+                                _ => bail!(r#"Unknown root element "{}""#, String::from_utf8_lossy(&name)),
+                            });
+                        },
+                        (1, Some(kind)) if name == kind.entry() => entry = Some(PartialEntry::default()),
+                        (2, _) if name == b"video:video" => {
+                            if let Some(entry) = &mut entry {
+                                entry.videos.push(PartialVideo::default());
+                            }
+                        },
+                        (2, _) if name == b"xhtml:link" => {
+                            if let Some(entry) = &mut entry {
+                                let (mut rel, mut hreflang, mut href) = (None, None, None);
+                                for attribute in element.attributes() {
+                                    let attribute = attribute?;
+                                    let value = Some(attribute.unescape_and_decode_value(&reader)?);
+                                    match attribute.key {
+                                        b"rel" => rel = value,
+                                        b"hreflang" => hreflang = value,
+                                        b"href" => href = value,
+                                        _ => {},
+                                    }
+                                }
+                                if let (Some("alternate"), Some(hreflang), Some(href)) = (rel.as_deref(), hreflang, href) {
+                                    entry.alternates.push((hreflang, href));
+                                }
+                            }
+                        },
+                        _ => {},
+                    }
+                    if matches!(event, Event::Start(_)) {
+                        path.push(name);
+                    }
+                },
+                Event::End(_) => {
+                    path.pop();
+                    if path.len() == 1 {
+                        if let Some(partial) = entry.take() {
+                            entries.push(partial.finish()?);
+                        }
+                    }
+                },
+                Event::Text(ref text) | Event::CData(ref text) => {
+                    if let Some(entry) = &mut entry {
+                        let text = match event {
+                            Event::CData(_) => reader.decode(text.escaped())?.to_owned(),
+                            _ => text.unescape_and_decode(&reader)?,
+                        };
+                        match &path[2..] {
+                            [name] => entry.set(name, text),
+                            [image, loc] if image == b"image:image" && loc == b"image:loc" => entry.images.push(text),
+                            [video, name] if video == b"video:video" => {
+                                if let Some(video) = entry.videos.last_mut() {
+                                    video.set(name, text);
+                                }
+                            },
+                            [news, name] | [news, _, name] if news == b"news:news" => entry.news.get_or_insert_with(Default::default).set(name, text),
+                            _ => {},
+                        }
+                    }
+                },
+                Event::Eof => break,
+                _ => {},
+            }
+            buf.clear();
+        }
+
+        Ok(Sitemap {
+            kind: kind.ok_or_else(|| anyhow!("The sitemap is empty"))?,
+            entries,
+        })
+    }
+
+    /// Writes the sitemap as XML
+    pub fn write(&self, writer: impl Write) -> Result<()> {
+        let mut writer = Writer::new_with_indent(writer, b' ', 2);
+        writer.write_event(Event::Decl(BytesDecl::new(b"1.0", Some(b"UTF-8"), None)))?;
+        let mut root = BytesStart::borrowed_name(self.kind.root()).with_attributes([("xmlns", SITEMAP_NAMESPACE)]);
+        // Extension namespaces are declared only when used
+        if self.entries.iter().any(|entry| !entry.images.is_empty()) {
+            root.push_attribute(("xmlns:image", IMAGE_NAMESPACE));
+        }
+        if self.entries.iter().any(|entry| entry.videos.iter().any(Video::is_complete)) {
+            root.push_attribute(("xmlns:video", VIDEO_NAMESPACE));
+        }
+        if self.entries.iter().any(|entry| entry.news.is_some()) {
+            root.push_attribute(("xmlns:news", NEWS_NAMESPACE));
+        }
+        if self.entries.iter().any(|entry| !entry.alternates.is_empty()) {
+            root.push_attribute(("xmlns:xhtml", XHTML_NAMESPACE));
+        }
+        writer.write_event(Event::Start(root))?;
+        for entry in &self.entries {
+            writer.write_event(Event::Start(BytesStart::borrowed_name(self.kind.entry())))?;
+            write_element(&mut writer, b"loc", Some(entry.loc.as_str()))?;
+            write_element(&mut writer, b"lastmod", entry.lastmod.as_deref())?;
+            write_element(&mut writer, b"changefreq", entry.changefreq.as_deref())?;
+            write_element(&mut writer, b"priority", entry.priority.as_deref())?;
+            for alternate in &entry.alternates {
+                writer.write_event(Event::Empty(BytesStart::borrowed_name(b"xhtml:link").with_attributes([
+                    ("rel", "alternate"),
+                    ("hreflang", &alternate.hreflang),
+                    ("href", alternate.href.as_str()),
+                ])))?;
+            }
+            for image in entry.images.iter().take(MAX_IMAGES_PER_PAGE) {
+                writer.write_event(Event::Start(BytesStart::borrowed_name(b"image:image")))?;
+                write_element(&mut writer, b"image:loc", Some(image.as_str()))?;
+                writer.write_event(Event::End(BytesEnd::borrowed(b"image:image")))?;
+            }
+            for video in entry.videos.iter().filter(|video| video.is_complete()) {
+                writer.write_event(Event::Start(BytesStart::borrowed_name(b"video:video")))?;
+                write_element(&mut writer, b"video:thumbnail_loc", video.thumbnail_loc.as_ref().map(Url::as_str))?;
+                write_element(&mut writer, b"video:title", video.title.as_deref())?;
+                write_element(&mut writer, b"video:description", video.description.as_deref())?;
+                write_element(&mut writer, b"video:content_loc", video.content_loc.as_ref().map(Url::as_str))?;
+                write_element(&mut writer, b"video:player_loc", video.player_loc.as_ref().map(Url::as_str))?;
+                write_element(&mut writer, b"video:duration", video.duration.map(|duration| duration.to_string()).as_deref())?;
+                writer.write_event(Event::End(BytesEnd::borrowed(b"video:video")))?;
+            }
+            if let Some(news) = &entry.news {
+                writer.write_event(Event::Start(BytesStart::borrowed_name(b"news:news")))?;
+                writer.write_event(Event::Start(BytesStart::borrowed_name(b"news:publication")))?;
+                write_element(&mut writer, b"news:name", Some(&news.publication_name))?;
+                write_element(&mut writer, b"news:language", Some(&news.language))?;
+                writer.write_event(Event::End(BytesEnd::borrowed(b"news:publication")))?;
+                write_element(&mut writer, b"news:publication_date", Some(&news.publication_date))?;
+                write_element(&mut writer, b"news:title", Some(&news.title))?;
+                writer.write_event(Event::End(BytesEnd::borrowed(b"news:news")))?;
+            }
+            writer.write_event(Event::End(BytesEnd::borrowed(self.kind.entry())))?;
+        }
+        writer.write_event(Event::End(BytesEnd::borrowed(self.kind.root())))?;
+        writer.inner().write_all(b"\n")?;
+        Ok(())
+    }
+
+    /// Writes the URLs as text sitemaps, one per line, starting a new one when
+    /// [`MAX_SITEMAP_ENTRIES`] or [`MAX_SITEMAP_SIZE`] would be exceeded. There is always at least one part
+    pub fn text_parts(&self) -> Vec<String> {
+        let mut parts = Vec::new();
+        let mut current = String::new();
+        let mut lines = 0;
+        for entry in &self.entries {
+            let url = entry.loc.as_str();
+            if lines == MAX_SITEMAP_ENTRIES || current.len() + url.len() + 1 > MAX_SITEMAP_SIZE {
+                parts.push(std::mem::take(&mut current));
+                lines = 0;
+            }
+            current.push_str(url);
+            current.push('\n');
+            lines += 1;
+        }
+        parts.push(current);
+        parts
+    }
+
+    /// The most recent `lastmod` of the entries, comparing the dates they represent rather than how they are written
+    pub fn latest_lastmod(&self) -> Option<&str> {
+        self.entries.iter()
+        .filter_map(|entry| entry.lastmod.as_deref())
+        .reduce(|latest, lastmod| if is_more_recent(Some(lastmod), Some(latest)) { lastmod } else { latest })
+    }
+
+    /// Combines sitemaps of the same kind, removing duplicated entries.
+    /// When an entry is present more than once, the most recent `lastmod` is kept and the images and videos are combined.
+    pub fn merge(sitemaps: impl IntoIterator<Item=Sitemap>) -> Result<Sitemap> {
+        let mut kind = None;
+        let mut entries: Vec<SitemapEntry> = Vec::new();
+        let mut indexes: HashMap<Url, usize> = HashMap::new();
+        for sitemap in sitemaps {
+            if *kind.get_or_insert(sitemap.kind) != sitemap.kind {
+                bail!("Cannot merge urlsets with sitemap indexes");
+            }
+            for entry in sitemap.entries {
+                match indexes.get(&entry.loc) {
+                    Some(&index) => {
+                        let existing = &mut entries[index];
+                        if is_more_recent(entry.lastmod.as_deref(), existing.lastmod.as_deref()) {
+                            existing.lastmod = entry.lastmod;
+                        }
+                        for image in entry.images {
+                            if !existing.images.contains(&image) {
+                                existing.images.push(image);
+                            }
+                        }
+                        for video in entry.videos {
+                            if !existing.videos.contains(&video) {
+                                existing.videos.push(video);
+                            }
+                        }
+                        existing.news = existing.news.take().or(entry.news);
+                        for alternate in entry.alternates {
+                            if !existing.alternates.contains(&alternate) {
+                                existing.alternates.push(alternate);
+                            }
+                        }
+                    },
+                    None => {
+                        indexes.insert(entry.loc.clone(), entries.len());
+                        entries.push(entry);
+                    },
+                }
+            }
+        }
+        Ok(Sitemap {
+            kind: kind.unwrap_or(SitemapKind::UrlSet),
+            entries,
+        })
+    }
+
+    /// Compares the URLs of two sitemaps and their last modification dates
+    pub fn diff(&self, new: &Sitemap) -> SitemapDiff {
+        let old_urls: HashMap<&Url, &SitemapEntry> = self.entries.iter().map(|entry| (&entry.loc, entry)).collect();
+        let new_urls: HashMap<&Url, &SitemapEntry> = new.entries.iter().map(|entry| (&entry.loc, entry)).collect();
+
+        let mut added: Vec<Url> = new_urls.keys().filter(|url| !old_urls.contains_key(*url)).map(|url| (*url).clone()).collect();
+        let mut removed: Vec<Url> = old_urls.keys().filter(|url| !new_urls.contains_key(*url)).map(|url| (*url).clone()).collect();
+        let mut changed: Vec<LastmodChange> = new.entries.iter().filter_map(|entry| {
+            let old = old_urls.get(&entry.loc)?;
+            let (old_lastmod, new_lastmod) = (old.lastmod.as_ref()?, entry.lastmod.as_ref()?);
+            // Compare the dates themselves, since they can be written in different ways
+            let changed = match (parse_w3c_datetime(old_lastmod), parse_w3c_datetime(new_lastmod)) {
+                (Some(old), Some(new)) => old != new,
+                _ => old_lastmod != new_lastmod,
+            };
+            changed.then(|| LastmodChange {
+                url: entry.loc.clone(),
+                old_lastmod: old_lastmod.clone(),
+                new_lastmod: new_lastmod.clone(),
+            })
+        }).collect();
+
+        added.sort();
+        removed.sort();
+        changed.sort_by(|first, second| first.url.cmp(&second.url));
+        changed.dedup_by(|first, second| first.url == second.url);
+        SitemapDiff {
+            added,
+            removed,
+            changed,
+        }
+    }
+}
+
+/// The differences between two sitemaps, see [`Sitemap::diff`]. URLs are sorted.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct SitemapDiff {
+    pub added: Vec<Url>,
+    pub removed: Vec<Url>,
+    /// URLs present in both sitemaps whose `lastmod` is different
+    pub changed: Vec<LastmodChange>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LastmodChange {
+    pub url: Url,
+    pub old_lastmod: String,
+    pub new_lastmod: String,
+}
+
+impl SitemapDiff {
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Downloads a sitemap, following redirects. The sitemaps listed by a sitemap index are downloaded and merged.
+pub async fn fetch_sitemap<F: Fetcher>(fetcher: &F, url: &Url) -> Result<Sitemap> {
+    let sitemap = fetch_single_sitemap(fetcher, url).await?;
+    if sitemap.kind == SitemapKind::UrlSet {
+        return Ok(sitemap);
+    }
+
+    let mut sitemaps = Vec::with_capacity(sitemap.entries.len());
+    for entry in &sitemap.entries {
+        let sitemap = fetch_single_sitemap(fetcher, &entry.loc).await?;
+        if sitemap.kind == SitemapKind::Index {
+            bail!(r#""{}" is a sitemap index listed by another sitemap index"#, entry.loc);
+        }
+        sitemaps.push(sitemap);
+    }
+    Sitemap::merge(sitemaps)
+}
+
+async fn fetch_single_sitemap<F: Fetcher>(fetcher: &F, url: &Url) -> Result<Sitemap> {
+    let mut url = url.clone();
+    for _ in 0..=MAX_REDIRECTS {
+        let response = fetcher.fetch(&url).await.with_context(|| format!(r#"Cannot download "{}""#, url))?;
+        if response.status.is_redirection() {
+            if let Some(location) = response.headers.get(LOCATION).and_then(|location| location.to_str().ok()) {
+                url = response.url.join(location)?;
+                continue;
+            }
+        }
+        if !response.status.is_success() {
+            bail!(r#"Cannot download "{}": {}"#, url, response.status);
+        }
+        let xml = response.bytes().await.with_context(|| format!(r#"Cannot download "{}""#, url))?;
+        return Sitemap::parse(&xml).with_context(|| format!(r#"Invalid sitemap "{}""#, url));
+    }
+    bail!(r#"Too many redirects downloading "{}""#, url)
+}
+
+/// Compares two `lastmod` values, which can be written in different ways
+fn is_more_recent(lastmod: Option<&str>, other: Option<&str>) -> bool {
+    match (lastmod.and_then(parse_w3c_datetime), other.and_then(parse_w3c_datetime)) {
+        (Some(lastmod), Some(other)) => lastmod > other,
+        _ => lastmod > other,
+    }
+}
+
+pub(crate) fn write_element<W: Write>(writer: &mut Writer<W>, name: &[u8], text: Option<&str>) -> Result<()> {
+    if let Some(text) = text {
+        writer.write_event(Event::Start(BytesStart::borrowed_name(name)))?;
+        writer.write_event(Event::Text(BytesText::from_plain_str(text)))?;
+        writer.write_event(Event::End(BytesEnd::borrowed(name)))?;
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+struct PartialEntry {
+    loc: Option<String>,
+    lastmod: Option<String>,
+    changefreq: Option<String>,
+    priority: Option<String>,
+    images: Vec<String>,
+    videos: Vec<PartialVideo>,
+    news: Option<PartialNews>,
+    /// Language and URL
+    alternates: Vec<(String, String)>,
+}
+
+impl PartialEntry {
+    fn set(&mut self, name: &[u8], text: String) {
+        let field = match name {
+            b"loc" => &mut self.loc,
+            b"lastmod" => &mut self.lastmod,
+            b"changefreq" => &mut self.changefreq,
+            b"priority" => &mut self.priority,
+            _ => return,
+        };
+        *field = Some(text);
+    }
+
+    fn finish(self) -> Result<SitemapEntry> {
+        let loc = self.loc.ok_or_else(|| anyhow!("Missing <loc> element"))?;
+        Ok(SitemapEntry {
+            loc: parse_url(&loc, "loc")?,
+            lastmod: self.lastmod,
+            changefreq: self.changefreq,
+            priority: self.priority,
+            images: self.images.iter().map(|image| parse_url(image, "image:loc")).collect::<Result<_>>()?,
+            videos: self.videos.into_iter().map(PartialVideo::finish).collect::<Result<_>>()?,
+            news: self.news.map(PartialNews::finish).transpose()?,
+            alternates: self.alternates.into_iter().map(|(hreflang, href)| Ok(Alternate {
+                href: parse_url(&href, "xhtml:link")?,
+                hreflang,
+            })).collect::<Result<_>>()?,
+        })
+    }
+}
+
+#[derive(Default)]
+struct PartialNews {
+    publication_name: Option<String>,
+    language: Option<String>,
+    publication_date: Option<String>,
+    title: Option<String>,
+}
+
+impl PartialNews {
+    fn set(&mut self, name: &[u8], text: String) {
+        let field = match name {
+            b"news:name" => &mut self.publication_name,
+            b"news:language" => &mut self.language,
+            b"news:publication_date" => &mut self.publication_date,
+            b"news:title" => &mut self.title,
+            _ => return,
+        };
+        *field = Some(text);
+    }
+
+    fn finish(self) -> Result<News> {
+        let missing = |element: &str| anyhow!("Missing <{}> element", element);
+        Ok(News {
+            publication_name: self.publication_name.ok_or_else(|| missing("news:name"))?,
+            language: self.language.ok_or_else(|| missing("news:language"))?,
+            publication_date: self.publication_date.ok_or_else(|| missing("news:publication_date"))?,
+            title: self.title.ok_or_else(|| missing("news:title"))?,
+        })
+    }
+}
+
+#[derive(Default)]
+struct PartialVideo {
+    thumbnail_loc: Option<String>,
+    title: Option<String>,
+    description: Option<String>,
+    content_loc: Option<String>,
+    player_loc: Option<String>,
+    duration: Option<String>,
+}
+
+impl PartialVideo {
+    fn set(&mut self, name: &[u8], text: String) {
+        let field = match name {
+            b"video:thumbnail_loc" => &mut self.thumbnail_loc,
+            b"video:title" => &mut self.title,
+            b"video:description" => &mut self.description,
+            b"video:content_loc" => &mut self.content_loc,
+            b"video:player_loc" => &mut self.player_loc,
+            b"video:duration" => &mut self.duration,
+            _ => return,
+        };
+        *field = Some(text);
+    }
+
+    fn finish(self) -> Result<Video> {
+        Ok(Video {
+            thumbnail_loc: self.thumbnail_loc.map(|url| parse_url(&url, "video:thumbnail_loc")).transpose()?,
+            title: self.title,
+            description: self.description,
+            content_loc: self.content_loc.map(|url| parse_url(&url, "video:content_loc")).transpose()?,
+            player_loc: self.player_loc.map(|url| parse_url(&url, "video:player_loc")).transpose()?,
+            duration: self.duration.map(|duration| duration.parse().with_context(|| format!(r#"Invalid <video:duration> "{}""#, duration))).transpose()?,
+        })
+    }
+}
+
+#[inline]
+fn parse_url(url: &str, element: &str) -> Result<Url> {
+    Url::parse(url).with_context(|| format!(r#"Invalid <{}> "{}""#, element, url))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parses a `<urlset>` holding `url`, checking that writing it back gives the same sitemap
+    fn round_trip(namespaces: &str, url: &str) -> (Sitemap, String) {
+        let xml = format!(r#"<?xml version="1.0" encoding="UTF-8"?>
+<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9"{}>
+  <url>
+    <loc>https://example.com/</loc>{}
+  </url>
+</urlset>"#, namespaces, url);
+        let sitemap = Sitemap::parse(xml.as_bytes()).unwrap();
+        let mut written = Vec::new();
+        sitemap.write(&mut written).unwrap();
+        assert_eq!(Sitemap::parse(&written).unwrap(), sitemap);
+        (sitemap, String::from_utf8(written).unwrap())
+    }
+
+    #[test]
+    fn sitemap_round_trip() {
+        let xml = br#"<?xml version="1.0" encoding="UTF-8"?>
+<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+  <url>
+    <loc>https://example.com/?a=1&amp;b=2</loc>
+    <lastmod>2022-03-01</lastmod>
+  </url>
+  <url><loc>https://example.com/page</loc></url>
+</urlset>"#;
+        let sitemap = Sitemap::parse(xml).unwrap();
+        assert_eq!(sitemap.kind, SitemapKind::UrlSet);
+        assert_eq!(sitemap.entries.len(), 2);
+        assert_eq!(sitemap.entries[0].loc.as_str(), "https://example.com/?a=1&b=2");
+        assert_eq!(sitemap.entries[0].lastmod.as_deref(), Some("2022-03-01"));
+        assert_eq!(sitemap.entries[1].lastmod, None);
+
+        let mut written = Vec::new();
+        sitemap.write(&mut written).unwrap();
+        assert!(String::from_utf8_lossy(&written).contains("<loc>https://example.com/?a=1&amp;b=2</loc>"));
+        assert_eq!(Sitemap::parse(&written).unwrap(), sitemap);
+    }
+
+    #[test]
+    fn image_round_trip() {
+        let (sitemap, written) = round_trip(
+            r#" xmlns:image="http://www.google.com/schemas/sitemap-image/1.1""#,
+            "<image:image><image:loc>https://example.com/image.png</image:loc></image:image>",
+        );
+        assert_eq!(sitemap.entries[0].images, [Url::parse("https://example.com/image.png").unwrap()]);
+        assert!(written.contains(r#"xmlns:image="http://www.google.com/schemas/sitemap-image/1.1""#));
+    }
+
+    #[test]
+    fn video_round_trip() {
+        let (sitemap, _) = round_trip(r#" xmlns:video="http://www.google.com/schemas/sitemap-video/1.1""#, r#"
+    <video:video>
+      <video:thumbnail_loc>https://example.com/thumbnail.jpg</video:thumbnail_loc>
+      <video:title>Tutorial</video:title>
+      <video:description>How to &lt;crawl&gt;</video:description>
+      <video:content_loc>https://example.com/tutorial.mp4</video:content_loc>
+      <video:duration>90</video:duration>
+    </video:video>"#);
+        let video = &sitemap.entries[0].videos[0];
+        assert!(video.is_complete());
+        assert_eq!(video.title.as_deref(), Some("Tutorial"));
+        assert_eq!(video.description.as_deref(), Some("How to <crawl>"));
+        assert_eq!(video.duration, Some(90));
+    }
+
+    #[test]
+    fn news_round_trip() {
+        let (sitemap, _) = round_trip(r#" xmlns:news="http://www.google.com/schemas/sitemap-news/0.9""#, r#"
+    <news:news>
+      <news:publication><news:name>Example</news:name><news:language>en</news:language></news:publication>
+      <news:publication_date>2022-03-01</news:publication_date>
+      <news:title>Released</news:title>
+    </news:news>"#);
+        let news = sitemap.entries[0].news.as_ref().unwrap();
+        assert_eq!(news.publication_name, "Example");
+        assert_eq!(news.language, "en");
+        assert_eq!(news.title, "Released");
+    }
+
+    #[test]
+    fn hreflang_round_trip() {
+        let (sitemap, _) = round_trip(
+            r#" xmlns:xhtml="http://www.w3.org/1999/xhtml""#,
+            r#"<xhtml:link rel="alternate" hreflang="de" href="https://example.com/de/?a=1&amp;b=2"/>"#,
+        );
+        let alternate = &sitemap.entries[0].alternates[0];
+        assert_eq!(alternate.hreflang, "de");
+        assert_eq!(alternate.href.as_str(), "https://example.com/de/?a=1&b=2");
+    }
+
+    #[test]
+    fn text_sitemap_parts() {
+        let urls = (0..MAX_SITEMAP_ENTRIES + 1).map(|index| Url::parse(&format!("https://example.com/{}", index)).unwrap());
+        let parts = Sitemap::from_urls(urls).text_parts();
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].lines().count(), MAX_SITEMAP_ENTRIES);
+        assert!(parts[0].starts_with("https://example.com/0\nhttps://example.com/1\n"));
+        assert_eq!(parts[1], format!("https://example.com/{}\n", MAX_SITEMAP_ENTRIES));
+
+        let long_url = Url::parse(&format!("https://example.com/{}", "a".repeat(MAX_SITEMAP_SIZE / 3))).unwrap();
+        let parts = Sitemap::from_urls([long_url.clone(), long_url.clone(), long_url]).text_parts();
+        assert_eq!(parts.iter().map(|part| part.lines().count()).collect::<Vec<_>>(), [2, 1]);
+        assert!(parts.iter().all(|part| part.len() <= MAX_SITEMAP_SIZE));
+
+        assert_eq!(Sitemap::from_urls([]).text_parts(), [""]);
+    }
+
+    #[test]
+    fn latest_lastmod() {
+        let sitemap = Sitemap {
+            kind: SitemapKind::UrlSet,
+            entries: ["2022-04-01", "2022-03-31T23:00:00-02:00", "2022-04-01T00:30:00+01:00"].into_iter().enumerate().map(|(index, lastmod)| SitemapEntry {
+                lastmod: Some(lastmod.to_string()),
+                ..SitemapEntry::new(Url::parse(&format!("https://example.com/{}", index)).unwrap())
+            }).collect(),
+        };
+        // Comparing the strings would give "2022-04-01T00:30:00+01:00", which is earlier
+        assert_eq!(sitemap.latest_lastmod(), Some("2022-03-31T23:00:00-02:00"));
+        assert_eq!(Sitemap::from_urls([]).latest_lastmod(), None);
+    }
+
+    fn sitemap_of(entries: &[(&str, Option<&str>)]) -> Sitemap {
+        Sitemap {
+            kind: SitemapKind::UrlSet,
+            entries: entries.iter().map(|(url, lastmod)| SitemapEntry {
+                lastmod: lastmod.map(str::to_string),
+                ..SitemapEntry::new(Url::parse(url).unwrap())
+            }).collect(),
+        }
+    }
+
+    #[test]
+    fn sitemap_merge() {
+        let old = sitemap_of(&[("https://example.com/", None), ("https://example.com/page", Some("2022-03-01"))]);
+        let new = sitemap_of(&[("https://example.com/page", Some("2022-04-01")), ("https://example.com/new", None)]);
+        let merged = Sitemap::merge([old, new]).unwrap();
+        let entries: Vec<(&str, Option<&str>)> = merged.entries.iter().map(|entry| (entry.loc.as_str(), entry.lastmod.as_deref())).collect();
+        assert_eq!(entries, [
+            ("https://example.com/", None),
+            ("https://example.com/page", Some("2022-04-01")),
+            ("https://example.com/new", None),
+        ]);
+    }
+
+    #[test]
+    fn sitemap_diff() {
+        let old = sitemap_of(&[("https://example.com/", None), ("https://example.com/page", None)]);
+        let new = sitemap_of(&[("https://example.com/page", Some("2022-04-01")), ("https://example.com/new", None)]);
+        let diff = old.diff(&new);
+        assert_eq!(diff.added, [Url::parse("https://example.com/new").unwrap()]);
+        assert_eq!(diff.removed, [Url::parse("https://example.com/").unwrap()]);
+        // A lastmod which wasn't known before isn't a change
+        assert!(diff.changed.is_empty());
+
+        let changed = sitemap_of(&[("https://example.com/page", Some("2022-04-02T00:00:00Z")), ("https://example.com/new", None)]);
+        assert_eq!(new.diff(&changed).changed, [LastmodChange {
+            url: Url::parse("https://example.com/page").unwrap(),
+            old_lastmod: "2022-04-01".to_string(),
+            new_lastmod: "2022-04-02T00:00:00Z".to_string(),
+        }]);
+        assert!(changed.diff(&changed).is_empty());
+    }
+}
@@ -0,0 +1,67 @@
+//! Helpers shared by the tests of the crate
+
+use std::sync::Arc;
+
+use reqwest::header::{CONTENT_TYPE, HeaderMap, HeaderName, HeaderValue};
+use reqwest::StatusCode;
+use tokio::sync::Notify;
+use url::Url;
+
+use crate::{analyze_with_fetcher, CrawlResult, Fetcher, FetchResponse, MemoryFetcher, Options, Validator};
+
+/// The site crawled by [`crawl`]
+pub(crate) const BASE_URL: &str = "https://example.com/";
+
+/// Parses `str`, which can be relative to [`BASE_URL`]
+pub(crate) fn url(str: &str) -> Url {
+    Url::parse(BASE_URL).unwrap().join(str).unwrap()
+}
+
+/// Crawls [`BASE_URL`], which is both the starting point and the only site to analyze
+pub(crate) async fn crawl(fetcher: impl Fetcher, options: Options) -> CrawlResult {
+    let validator = Validator::new(std::iter::once(url(BASE_URL)));
+    analyze_with_fetcher(fetcher, std::iter::once(url(BASE_URL)), validator, options).await
+}
+
+/// Serves `html` at `path` like [`MemoryFetcher::with_page`], adding the provided headers
+pub(crate) fn with_headers(fetcher: MemoryFetcher, path: &str, headers: &[(HeaderName, &'static str)], html: &'static str) -> MemoryFetcher {
+    let mut header_map = HeaderMap::new();
+    header_map.insert(CONTENT_TYPE, HeaderValue::from_static("text/html"));
+    for (name, value) in headers {
+        header_map.insert(name.clone(), HeaderValue::from_static(value));
+    }
+    fetcher.with_response(url(path), StatusCode::OK, header_map, html)
+}
+
+/// The sorted URLs of the sites found by a crawl
+pub(crate) fn sorted_sites(result: &CrawlResult) -> Vec<String> {
+    let mut sites: Vec<String> = result.sites.iter().map(|site| site.to_string()).collect();
+    sites.sort();
+    sites
+}
+
+/// Never answers requests to "/slow", used to interrupt crawls
+pub(crate) struct SlowFetcher {
+    fetcher: MemoryFetcher,
+    /// Notified when the request to "/slow" starts
+    pub(crate) started: Arc<Notify>,
+}
+
+impl SlowFetcher {
+    pub(crate) fn new(fetcher: MemoryFetcher) -> SlowFetcher {
+        SlowFetcher {
+            fetcher,
+            started: Default::default(),
+        }
+    }
+}
+
+impl Fetcher for SlowFetcher {
+    async fn fetch(&self, url: &Url) -> anyhow::Result<FetchResponse> {
+        if url.path() == "/slow" {
+            self.started.notify_one();
+            std::future::pending::<()>().await;
+        }
+        self.fetcher.fetch(url).await
+    }
+}
@@ -1,29 +1,11 @@
-use std::fmt::Display;
 use std::iter::{Filter, Map};
+use std::path::Path;
 
-use tokio::io::{AsyncWriteExt, stderr, stdout};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
 use url::Url;
 
-#[inline]
-pub async fn println(string: impl AsRef<str>) {
-    let _ = stdout().write(string.as_ref().as_bytes()).await;
-}
-
-#[inline]
-pub fn _verbose(site: impl AsRef<str> + Send + Sync + 'static) {
-    tokio::spawn(_verbose_async(site));
-}
-
-#[inline]
-pub async fn _verbose_async(site: impl AsRef<str>) {
-    println(format!("Analyzing: \"{}\"\n", site.as_ref())).await;
-}
-
-#[inline]
-pub async fn eprintln(error: impl Display, site: &str) {
-    let _ = stderr().write(format!("An error occurred analyzing \"{}\": {error}\n", site).as_bytes()).await;
-}
-
 pub trait UrlIteratorUtil: Iterator<Item=Url> + Sized {
     #[inline]
     fn normalize(self) -> Map<Self, fn(Url) -> Url> {
@@ -41,8 +23,7 @@ impl<It: Iterator<Item=Url> + Sized> UrlIteratorUtil for It {}
 #[inline]
 pub fn normalize(url: Url) -> Url {
     let url = url_normalizer::normalize_query(url);
-    let url = url_normalizer::normalize_hash(url);
-    url
+    url_normalizer::normalize_hash(url)
 }
 
 #[inline]
@@ -50,6 +31,16 @@ pub fn filter_http(url: &Url) -> bool {
     matches!(url.scheme(), "http" | "https")
 }
 
+/// Parses the value of an HTTP date header, like `Last-Modified`
+pub fn parse_http_date(date: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc2822(date).ok().map(|date| date.with_timezone(&Utc))
+}
+
+/// Formats a date for an HTTP header, e.g. `Sun, 06 Nov 1994 08:49:37 GMT`
+pub fn format_http_date(date: &DateTime<Utc>) -> String {
+    date.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
 #[macro_export]
 macro_rules! measure_time {
     (nano: $($code:tt)*) => { $crate::__internal_measure_time!{use as_nanos for $( $code )*} };
@@ -72,3 +63,18 @@ macro_rules! __internal_measure_time {
         r
     }};
 }
+
+/// Reads a JSON file, `kind` describes it in the error messages
+pub fn read_json<T: DeserializeOwned>(path: &Path, kind: &str) -> Result<T> {
+    let file = std::fs::read(path).with_context(|| format!(r#"Cannot read {} file "{}""#, kind, path.display()))?;
+    serde_json::from_slice(&file).with_context(|| format!(r#"Invalid {} file "{}""#, kind, path.display()))
+}
+
+/// Replaces the content of the file at `path` through a temporary file, so that it's never left half written
+pub fn write_atomically(path: &Path, content: &[u8]) -> Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    std::fs::write(&tmp, content)?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
@@ -0,0 +1,481 @@
+use std::fmt::{Display, Formatter};
+
+use chrono::{DateTime, FixedOffset, NaiveDate};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use url::Url;
+
+use crate::sitemap::SITEMAP_NAMESPACE;
+
+/// Max number of entries of a sitemap
+pub const MAX_SITEMAP_ENTRIES: usize = 50_000;
+/// Max size in bytes of an uncompressed sitemap
+pub const MAX_SITEMAP_SIZE: usize = 50 * 1024 * 1024;
+/// Max length of a `<loc>`
+pub const MAX_LOC_LENGTH: usize = 2048;
+
+const CHANGEFREQS: [&str; 7] = ["always", "hourly", "daily", "weekly", "monthly", "yearly", "never"];
+
+/// A violation of the sitemaps.org protocol found by [`validate_sitemap`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SitemapIssue {
+    /// Starting from 1. For issues about the whole file, it's the line of the root element
+    pub line: usize,
+    pub problem: SitemapProblem,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SitemapProblem {
+    /// The file isn't well-formed XML, the validation stops here
+    InvalidXml(String),
+    /// The root isn't `<urlset>` or `<sitemapindex>`
+    InvalidRoot(String),
+    /// The root doesn't declare the sitemaps.org namespace
+    InvalidNamespace(Option<String>),
+    /// An element which doesn't belong there
+    UnexpectedElement(String),
+    TooManyEntries(usize),
+    TooLarge(usize),
+    MissingLoc,
+    /// A required element is present more than once
+    DuplicateElement(String),
+    InvalidLoc(String),
+    RelativeLoc(String),
+    /// The `<loc>` contains characters which should be escaped
+    UnescapedLoc(String),
+    LocTooLong(usize),
+    InvalidLastmod(String),
+    InvalidChangefreq(String),
+    InvalidPriority(String),
+    /// The URL isn't on the host of the sitemap or isn't under its directory
+    OutsideSitemapLocation(String),
+}
+
+impl Display for SitemapProblem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SitemapProblem::InvalidXml(error) => write!(f, "invalid XML: {}", error),
+            SitemapProblem::InvalidRoot(root) => write!(f, r#"the root element must be <urlset> or <sitemapindex>, found <{}>"#, root),
+            SitemapProblem::InvalidNamespace(Some(namespace)) => write!(f, r#"invalid namespace "{}", expected "{}""#, namespace, SITEMAP_NAMESPACE),
+            SitemapProblem::InvalidNamespace(None) => write!(f, r#"missing namespace, expected xmlns="{}""#, SITEMAP_NAMESPACE),
+            SitemapProblem::UnexpectedElement(name) => write!(f, "unexpected element <{}>", name),
+            SitemapProblem::TooManyEntries(count) => write!(f, "{} entries, the limit is {}", count, MAX_SITEMAP_ENTRIES),
+            SitemapProblem::TooLarge(size) => write!(f, "{} bytes, the limit is {}", size, MAX_SITEMAP_SIZE),
+            SitemapProblem::MissingLoc => write!(f, "missing <loc>"),
+            SitemapProblem::DuplicateElement(name) => write!(f, "<{}> is present more than once", name),
+            SitemapProblem::InvalidLoc(loc) => write!(f, r#"invalid URL "{}""#, loc),
+            SitemapProblem::RelativeLoc(loc) => write!(f, r#""{}" is not an absolute URL"#, loc),
+            SitemapProblem::UnescapedLoc(loc) => write!(f, r#""{}" must be escaped"#, loc),
+            SitemapProblem::LocTooLong(length) => write!(f, "the URL is {} characters long, the limit is {}", length, MAX_LOC_LENGTH),
+            SitemapProblem::InvalidLastmod(lastmod) => write!(f, r#""{}" is not in the W3C Datetime format"#, lastmod),
+            SitemapProblem::InvalidChangefreq(changefreq) => write!(f, r#"invalid changefreq "{}", expected one of {}"#, changefreq, CHANGEFREQS.join(", ")),
+            SitemapProblem::InvalidPriority(priority) => write!(f, r#"invalid priority "{}", expected a number between 0.0 and 1.0"#, priority),
+            SitemapProblem::OutsideSitemapLocation(loc) => write!(f, r#""{}" is outside the location of the sitemap"#, loc),
+        }
+    }
+}
+
+impl Display for SitemapIssue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.problem)
+    }
+}
+
+/// Checks a urlset or a sitemap index against the [sitemaps.org protocol](https://www.sitemaps.org/protocol.html).
+///
+/// `location` is the URL where the sitemap is published: when provided, every URL must be on its host and under its directory.
+/// Elements of protocol extensions (with a namespace prefix) are ignored.
+pub fn validate_sitemap(xml: &[u8], location: Option<&Url>) -> Vec<SitemapIssue> {
+    let mut validator = SitemapValidator {
+        lines: Lines::new(xml),
+        location,
+        issues: Vec::new(),
+    };
+    validator.validate(xml);
+    validator.issues
+}
+
+struct SitemapValidator<'a> {
+    lines: Lines,
+    location: Option<&'a Url>,
+    issues: Vec<SitemapIssue>,
+}
+
+/// The element of an entry currently being read
+#[derive(Default)]
+struct EntryState {
+    line: usize,
+    locs: usize,
+    seen: Vec<Vec<u8>>,
+}
+
+impl SitemapValidator<'_> {
+    fn issue(&mut self, line: usize, problem: SitemapProblem) {
+        self.issues.push(SitemapIssue { line, problem });
+    }
+
+    fn validate(&mut self, xml: &[u8]) {
+        let mut reader = Reader::from_reader(xml);
+        reader.trim_text(true);
+
+        let mut entry_name: &[u8] = b"url";
+        let mut root_line = 1;
+        let mut depth = 0usize;
+        let mut entries = 0usize;
+        let mut entry: Option<EntryState> = None;
+        // The name of the child of the entry being read, if any
+        let mut field: Option<(Vec<u8>, usize)> = None;
+        let mut buf = Vec::new();
+        loop {
+            let event = reader.read_event(&mut buf);
+            // Position of the last byte of the event
+            let line = self.lines.line(reader.buffer_position().saturating_sub(1));
+            let event = match event {
+                Ok(event) => event,
+                Err(err) => {
+                    self.issue(line, SitemapProblem::InvalidXml(err.to_string()));
+                    return;
+                },
+            };
+
+            match event {
+                Event::Start(ref element) | Event::Empty(ref element) => {
+                    let empty = matches!(event, Event::Empty(_));
+                    let name = element.name();
+                    match depth {
+                        0 => {
+                            root_line = line;
+                            match name {
+                                b"urlset" => entry_name = b"url",
+                                b"sitemapindex" => entry_name = b"sitemap",
+                                _ => {
+                                    self.issue(line, SitemapProblem::InvalidRoot(String::from_utf8_lossy(name).into_owned()));
+                                    return;
+                                },
+                            }
+                            self.check_namespace(element, line);
+                        },
+                        1 if name == entry_name => {
+                            entries += 1;
+                            entry = Some(EntryState { line, ..EntryState::default() });
+                            if empty {
+                                self.issue(line, SitemapProblem::MissingLoc);
+                                entry = None;
+                            }
+                        },
+                        2 if !is_extension(name) => {
+                            let allowed: &[&[u8]] = if entry_name == b"url" {
+                                &[b"loc", b"lastmod", b"changefreq", b"priority"]
+                            } else {
+                                &[b"loc", b"lastmod"]
+                            };
+                            match &mut entry {
+                                Some(state) if allowed.contains(&name) => {
+                                    if state.seen.iter().any(|seen| seen == name) {
+                                        self.issue(line, SitemapProblem::DuplicateElement(String::from_utf8_lossy(name).into_owned()));
+                                    } else {
+                                        state.seen.push(name.to_vec());
+                                    }
+                                    if name == b"loc" {
+                                        state.locs += 1;
+                                    }
+                                    if !empty {
+                                        field = Some((name.to_vec(), line));
+                                    } else {
+                                        self.check_field(name, b"", line);
+                                    }
+                                },
+                                _ => self.issue(line, SitemapProblem::UnexpectedElement(String::from_utf8_lossy(name).into_owned())),
+                            }
+                        },
+                        1 if !is_extension(name) => self.issue(line, SitemapProblem::UnexpectedElement(String::from_utf8_lossy(name).into_owned())),
+                        _ => {},
+                    }
+                    if !empty {
+                        depth += 1;
+                    }
+                },
+                Event::Text(ref text) | Event::CData(ref text) => {
+                    if let Some((name, _)) = &field {
+                        let name = name.clone();
+                        if let Event::Text(_) = event {
+                            match text.unescaped() {
+                                Ok(unescaped) => self.check_field(&name, &unescaped, line),
+                                Err(err) => self.issue(line, SitemapProblem::InvalidXml(err.to_string())),
+                            }
+                        } else {
+                            self.check_field(&name, text.escaped(), line);
+                        }
+                        field = None;
+                    }
+                },
+                Event::End(_) => {
+                    depth = depth.saturating_sub(1);
+                    match depth {
+                        2 => {
+                            // An element without text
+                            if let Some((name, line)) = field.take() {
+                                self.check_field(&name, b"", line);
+                            }
+                        },
+                        1 => {
+                            if let Some(state) = entry.take() {
+                                if state.locs == 0 {
+                                    self.issue(state.line, SitemapProblem::MissingLoc);
+                                }
+                            }
+                        },
+                        _ => {},
+                    }
+                },
+                Event::Eof => break,
+                _ => {},
+            }
+            buf.clear();
+        }
+
+        if entries > MAX_SITEMAP_ENTRIES {
+            self.issue(root_line, SitemapProblem::TooManyEntries(entries));
+        }
+        if xml.len() > MAX_SITEMAP_SIZE {
+            self.issue(root_line, SitemapProblem::TooLarge(xml.len()));
+        }
+    }
+
+    fn check_namespace(&mut self, root: &BytesStart, line: usize) {
+        let namespace = root.attributes()
+        .filter_map(Result::ok)
+        .find(|attribute| attribute.key == b"xmlns")
+        .map(|attribute| String::from_utf8_lossy(&attribute.value).into_owned());
+        if namespace.as_deref() != Some(SITEMAP_NAMESPACE) {
+            self.issue(line, SitemapProblem::InvalidNamespace(namespace));
+        }
+    }
+
+    fn check_field(&mut self, name: &[u8], text: &[u8], line: usize) {
+        let text = String::from_utf8_lossy(text);
+        let text = text.trim();
+        match name {
+            b"loc" => self.check_loc(text, line),
+            b"lastmod" if !is_w3c_datetime(text) => self.issue(line, SitemapProblem::InvalidLastmod(text.to_string())),
+            b"changefreq" if !CHANGEFREQS.contains(&text) => self.issue(line, SitemapProblem::InvalidChangefreq(text.to_string())),
+            b"priority" if !is_priority(text) => self.issue(line, SitemapProblem::InvalidPriority(text.to_string())),
+            _ => {},
+        }
+    }
+
+    fn check_loc(&mut self, loc: &str, line: usize) {
+        if loc.len() > MAX_LOC_LENGTH {
+            self.issue(line, SitemapProblem::LocTooLong(loc.len()));
+        }
+        if loc.chars().any(|char| !char.is_ascii() || char.is_ascii_whitespace() || char.is_ascii_control() || "<>\"{}|\\^`".contains(char)) {
+            self.issue(line, SitemapProblem::UnescapedLoc(loc.to_string()));
+        }
+
+        let url = match Url::parse(loc) {
+            Ok(url) => url,
+            Err(url::ParseError::RelativeUrlWithoutBase) => {
+                self.issue(line, SitemapProblem::RelativeLoc(loc.to_string()));
+                return;
+            },
+            Err(_) => {
+                self.issue(line, SitemapProblem::InvalidLoc(loc.to_string()));
+                return;
+            },
+        };
+        if !matches!(url.scheme(), "http" | "https") {
+            self.issue(line, SitemapProblem::InvalidLoc(loc.to_string()));
+            return;
+        }
+
+        if let Some(location) = self.location {
+            if !is_under(&url, location) {
+                self.issue(line, SitemapProblem::OutsideSitemapLocation(loc.to_string()));
+            }
+        }
+    }
+}
+
+/// A sitemap can only contain URLs on its own host, inside the directory containing it
+fn is_under(url: &Url, location: &Url) -> bool {
+    let directory = match location.path().rfind('/') {
+        Some(index) => &location.path()[..=index],
+        None => "/",
+    };
+    url.scheme() == location.scheme()
+    && url.host_str() == location.host_str()
+    && url.port_or_known_default() == location.port_or_known_default()
+    && url.path().starts_with(directory)
+}
+
+/// Elements of protocol extensions, like `<image:image>`
+#[inline]
+fn is_extension(name: &[u8]) -> bool {
+    name.contains(&b':')
+}
+
+fn is_priority(priority: &str) -> bool {
+    !priority.is_empty()
+    && priority.chars().all(|char| char.is_ascii_digit() || char == '.')
+    && priority.parse::<f64>().is_ok_and(|priority| (0.0..=1.0).contains(&priority))
+}
+
+/// Checks the [W3C Datetime](https://www.w3.org/TR/NOTE-datetime) format:
+/// `YYYY`, `YYYY-MM`, `YYYY-MM-DD`, and `YYYY-MM-DDThh:mm[:ss[.s+]]TZD` where TZD is `Z` or `+hh:mm`/`-hh:mm`.
+pub fn is_w3c_datetime(datetime: &str) -> bool {
+    fn number(str: &str, len: usize, range: std::ops::RangeInclusive<u32>) -> bool {
+        str.len() == len && str.bytes().all(|byte| byte.is_ascii_digit()) && str.parse().is_ok_and(|number| range.contains(&number))
+    }
+
+    let (date, time) = match datetime.split_once('T') {
+        Some((date, time)) => (date, Some(time)),
+        None => (datetime, None),
+    };
+    let mut parts = date.split('-');
+    let valid_date = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(year), None, None, None) => number(year, 4, 0..=9999) && time.is_none(),
+        (Some(year), Some(month), None, None) => number(year, 4, 0..=9999) && number(month, 2, 1..=12) && time.is_none(),
+        (Some(year), Some(month), Some(day), None) => number(year, 4, 0..=9999) && number(month, 2, 1..=12) && number(day, 2, 1..=31)
+        // Rejects the days which don't exist in the month, like 2022-02-31
+        && NaiveDate::from_ymd_opt(year.parse().unwrap(), month.parse().unwrap(), day.parse().unwrap()).is_some(),
+        _ => false,
+    };
+    let time = match (valid_date, time) {
+        (false, _) => return false,
+        (true, None) => return true,
+        (true, Some(time)) => time,
+    };
+
+    let (time, zone) = match time.find(['Z', '+', '-']) {
+        Some(index) => time.split_at(index),
+        None => return false,
+    };
+    let valid_zone = zone == "Z" || match zone[1..].split_once(':') {
+        Some((hours, minutes)) => number(hours, 2, 0..=23) && number(minutes, 2, 0..=59),
+        None => false,
+    };
+
+    let mut parts = time.split(':');
+    let valid_time = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(hours), Some(minutes), None, None) => number(hours, 2, 0..=23) && number(minutes, 2, 0..=59),
+        (Some(hours), Some(minutes), Some(seconds), None) => {
+            let (seconds, fraction) = match seconds.split_once('.') {
+                Some((seconds, fraction)) => (seconds, Some(fraction)),
+                None => (seconds, None),
+            };
+            number(hours, 2, 0..=23) && number(minutes, 2, 0..=59) && number(seconds, 2, 0..=59)
+            && fraction.is_none_or(|fraction| !fraction.is_empty() && fraction.bytes().all(|byte| byte.is_ascii_digit()))
+        },
+        _ => false,
+    };
+    valid_zone && valid_time
+}
+
+/// Parses a date in the [W3C Datetime](https://www.w3.org/TR/NOTE-datetime) format.
+/// Dates without a time are considered to be at midnight UTC.
+pub fn parse_w3c_datetime(datetime: &str) -> Option<DateTime<FixedOffset>> {
+    if !is_w3c_datetime(datetime) {
+        return None;
+    }
+    let (date, time) = match datetime.split_once('T') {
+        Some((date, time)) => (date, time),
+        None => {
+            let date = match date_length(datetime) {
+                1 => format!("{}-01-01", datetime),
+                2 => format!("{}-01", datetime),
+                _ => datetime.to_string(),
+            };
+            return DateTime::parse_from_rfc3339(&format!("{}T00:00:00+00:00", date)).ok();
+        },
+    };
+    let (time, zone) = time.split_at(time.find(['Z', '+', '-'])?);
+    let zone = if zone == "Z" { "+00:00" } else { zone };
+    let seconds = if time.len() == "hh:mm".len() { ":00" } else { "" };
+    DateTime::parse_from_rfc3339(&format!("{}T{}{}{}", date, time, seconds, zone)).ok()
+}
+
+/// The number of components of a date
+#[inline]
+fn date_length(date: &str) -> usize {
+    date.split('-').count()
+}
+
+/// Converts byte positions into line numbers
+struct Lines {
+    // Positions of the newlines
+    newlines: Vec<usize>,
+}
+
+impl Lines {
+    fn new(xml: &[u8]) -> Lines {
+        Lines {
+            newlines: xml.iter().enumerate().filter(|(_, byte)| **byte == b'\n').map(|(index, _)| index).collect(),
+        }
+    }
+
+    #[inline]
+    fn line(&self, position: usize) -> usize {
+        self.newlines.partition_point(|newline| *newline < position) + 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sitemap_validation() {
+        let xml = br#"<?xml version="1.0" encoding="UTF-8"?>
+<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+  <url>
+    <loc>https://example.com/blog/</loc>
+    <lastmod>2022-03-01T10:00:00+01:00</lastmod>
+    <changefreq>weekly</changefreq>
+    <priority>0.8</priority>
+  </url>
+  <url>
+    <loc>/relative</loc>
+    <lastmod>01/03/2022</lastmod>
+    <changefreq>sometimes</changefreq>
+    <priority>2</priority>
+  </url>
+  <url>
+    <loc>https://example.com/a page</loc>
+  </url>
+  <url>
+    <loc>https://other.com/blog/</loc>
+    <loc>https://example.com/other/</loc>
+  </url>
+  <url><lastmod>2022</lastmod></url>
+</urlset>"#;
+        let location = Url::parse("https://example.com/blog/sitemap.xml").unwrap();
+        let issues: Vec<String> = validate_sitemap(xml, Some(&location)).iter().map(ToString::to_string).collect();
+        assert_eq!(issues, [
+            r#"line 10: "/relative" is not an absolute URL"#,
+            r#"line 11: "01/03/2022" is not in the W3C Datetime format"#,
+            r#"line 12: invalid changefreq "sometimes", expected one of always, hourly, daily, weekly, monthly, yearly, never"#,
+            r#"line 13: invalid priority "2", expected a number between 0.0 and 1.0"#,
+            r#"line 16: "https://example.com/a page" must be escaped"#,
+            r#"line 16: "https://example.com/a page" is outside the location of the sitemap"#,
+            r#"line 19: "https://other.com/blog/" is outside the location of the sitemap"#,
+            "line 20: <loc> is present more than once",
+            r#"line 20: "https://example.com/other/" is outside the location of the sitemap"#,
+            "line 22: missing <loc>",
+        ]);
+
+        let issues = validate_sitemap(br#"<urlset xmlns="http://www.google.com/schemas/sitemap/0.84"><url><loc>https://example.com/</loc></urlset>"#, None);
+        assert!(matches!(issues[0].problem, SitemapProblem::InvalidNamespace(Some(_))));
+        assert!(matches!(issues[1].problem, SitemapProblem::InvalidXml(_)));
+
+        assert!(is_w3c_datetime("1997-07-16T19:20:30.45Z"));
+        assert!(!is_w3c_datetime("1997-07-16T19:20"));
+        assert!(!is_w3c_datetime("1997-13"));
+        assert!(!is_w3c_datetime("2022-02-31"));
+        assert!(!is_w3c_datetime("2022-04-31T10:00:00Z"));
+        assert!(is_w3c_datetime("2024-02-29"));
+        assert_eq!(parse_w3c_datetime("2022-02-31"), None);
+        assert_eq!(parse_w3c_datetime("1997-07-16T19:20+01:00"), DateTime::parse_from_rfc3339("1997-07-16T18:20:00Z").ok());
+        assert_eq!(parse_w3c_datetime("1997"), DateTime::parse_from_rfc3339("1997-01-01T00:00:00Z").ok());
+    }
+}